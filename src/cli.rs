@@ -0,0 +1,1013 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CliError;
+
+type Result<T> = std::result::Result<T, CliError>;
+
+pub const USAGE: &str = "\
+Usage: kpfr [OPTIONS] [SERVICE] [-- <KUBECTL_ARGS>...]
+       kpfr [OPTIONS] <COMMAND>
+
+Commands:
+  validate     Check the remembered config against the cluster without forwarding
+  config edit  Open the config in $VISUAL/$EDITOR and check it still parses
+  config forget <SERVICE>|--all-ports
+               Remove the remembered ports of the service ([<namespace>/]<service>)
+               or of all services, keeping the other settings
+
+Arguments:
+  [SERVICE]  Service to forward, optionally as [<context>/]<namespace>/<service>[:<ports>]
+             (skips the service selection, <ports> like --ports, e.g. staging/web/api:80:8080)
+  [KUBECTL_ARGS]...
+             Extra arguments passed verbatim to kubectl (to port-forward unless --pass-to says otherwise)
+
+Options:
+      --context <NAME>       Use the given kubectl context without prompting
+  -n, --namespace <NAME>     Use the given namespace without prompting
+      --set-default-namespace
+                             Also make the namespace kubectl's default for the context
+      --context-glob <GLOB>  Use the only context matching the pattern (e.g. '*prod*')
+  -i, --interactive          Pick from the matching contexts if several match --context-glob
+      --context-sort <ORDER> Order of the context selection: alphabetical (default), recent
+                             or current-first (config: contextSort)
+      --no-context-select    Use the current context without prompting (config: skipContext)
+      --context-only         Only select (and switch to) the context, then exit
+      --ports-file <FILE>    Read port mappings ({\"service\": {\"remote\": local}}) from a JSON file,
+                             skipping the port selection for the covered services
+      --ports <MAPPINGS>     Ports to forward as remote:local pairs, skipping the port selection
+                             (e.g. 80:8080,443 or ranges like 8000-8002:9000-9002)
+      --ports-from-env <PREFIX>
+                             Read the ports to forward from <PREFIX>_<REMOTE>=<LOCAL> variables
+      --stdin-ports          Read remote:local port mappings from stdin, one or more per line
+      --address <IP>         Local address to listen on (remembered, except 0.0.0.0)
+      --allow-privileged     Don't warn about local ports below 1024, which usually need elevated
+                             privileges
+      --job <NAME>           Forward to the running (or else newest) pod of the job instead of a
+                             service, offering the container ports
+      --cronjob <NAME>       Forward to the pod of the newest job the cronjob created
+      --use-target-port      Forward to the target ports of a ready pod behind the service directly,
+                             bypassing the service (and its load balancing)
+      --rotate-pod           Forward to a different ready pod on every reconnect, round-robin
+                             (for HA testing, implies --use-target-port)
+      --wait                 Wait for a ready pod behind the service instead of only warning
+      --describe             Show the type, ports, selector and ready pods of the service before
+                             selecting the ports
+      --reuse                Forward the last service with its remembered ports without prompting,
+                             unless the ports the service exposes changed since
+      --interactive-ports-only
+                             Forward the last service in the context it was last forwarded in
+                             without prompting for them, only select the ports
+      --ephemeral            Use the remembered selections but leave the config untouched
+      --replace              Stop a running kpfr forwarding any of the same local ports
+      --duration <DURATION>  Stop forwarding after the given duration (e.g. 90s, 5m, 1h)
+      --request-timeout <DURATION>
+                             Let kubectl give up listing namespaces and services after the
+                             duration (passed as kubectl's --request-timeout, not applied to
+                             the port-forward itself)
+      --pass-to <CALLS>      kubectl calls receiving the arguments after `--`: forward (default),
+                             list or all
+      --as <USER>            Impersonate the user in all kubectl calls (RBAC must allow the
+                             impersonation)
+      --as-group <GROUP>     Impersonate the group in all kubectl calls, repeatable
+      --insecure-skip-tls-verify
+                             DANGEROUS: Don't verify the cluster's TLS certificate
+                             (only for throwaway dev clusters)
+      --select-first         Never prompt, take the current/remembered or first (sorted) item at
+                             every selection and all ports of the service
+      --strict               Never prompt, fail listing the candidates whenever a choice isn't
+                             determined by the arguments or the remembered config (e.g. in CI)
+      --live-picker          Reload the services every few seconds while the service selection
+                             is shown (filtering by substring)
+      --confirm-single-port  Show the port selection also for services with a single port
+                             (config: autoSelectSinglePort)
+      --no-fuzzy             Use plain arrow-key selection instead of fuzzy matching
+  -A, --all-namespaces       Select from the services of all namespaces
+      --namespaces <NAMES>   Select from the services of the given namespaces (e.g. web,api,jobs)
+      --service-type <TYPE>  Only list services of the type (e.g. ClusterIP, LoadBalancer)
+      --display-column <PATH>
+                             Show the value at the dotted path next to each service in the
+                             selection (e.g. '{.spec.type}' or 'metadata.labels.team')
+      --namespace-selector <SELECTOR>
+                             Only list namespaces matching the label selector (e.g. team=payments),
+                             remembered for the next runs (pass \"\" to clear)
+      --reconnect            Reconnect when the port-forward exits (up to 5 times)
+      --max-reconnects <N>   Reconnect at most N times before giving up (implies --reconnect)
+      --health-check         Periodically probe the local ports and reconnect broken forwards
+      --logs                 Also stream the logs of the pods behind the service, prefixed by pod
+      --keepalive <DURATION> Open a short-lived connection through every forwarded port at the
+                             interval (e.g. 30s), so idle tunnels aren't closed in between
+      --env-file <FILE>      Set <SERVICE>_HOST and <SERVICE>_PORT variables in the dotenv file
+      --session-log <FILE>   Append a JSON line describing the session to the file when it ends
+      --no-color             Don't use colors (like setting NO_COLOR)
+      --no-hyperlinks        Don't render forwarded HTTP ports as clickable links
+      --suggest-hosts        Print the /etc/hosts line to reach the forward as <service>.local
+                             (kpfr never edits the file)
+      --show-context         Print the resolved context, cluster and user before forwarding
+      --print-ready-json     Print a JSON line with the actually bound local ports to stdout once
+                             the forward is ready (e.g. for local port 0, like 80:0)
+  -o, --output <FORMAT>      Output format: human (default), json (errors), table (forwarded ports)
+                             or yaml (forwarded ports and errors)
+  -q, --quiet                Don't print the summary and the live status line
+  -v, --verbose              Print additional information
+      --print-config-path    Print the path of the config file
+      --config-format <FORMAT>
+                             Config file to use: json (config.json) or toml (config.toml), by
+                             default config.toml if it's the only one present
+      --completions <SHELL>  Print the shell completion script (bash)
+  -h, --help                 Print help
+  -V, --version              Print version, git commit and kubectl version
+
+Environment:
+  KPFR_CONTEXT, KPFR_NAMESPACE, KPFR_SERVICE, KPFR_PORTS
+             Defaults for --context, --namespace, [SERVICE] and --ports, overriding the
+             remembered config but not the command line
+  KUBECONFIG Passed on to kubectl as usual (shown with --verbose)";
+
+/// Shell completion script, completing services from the remembered config
+const BASH_COMPLETION: &str = r#"_kpfr() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        --completions) COMPREPLY=($(compgen -W "bash" -- "$cur")); return ;;
+        -o|--output) COMPREPLY=($(compgen -W "human json table yaml" -- "$cur")); return ;;
+        --pass-to) COMPREPLY=($(compgen -W "forward list all" -- "$cur")); return ;;
+        --config-format) COMPREPLY=($(compgen -W "json toml" -- "$cur")); return ;;
+        --context-sort) COMPREPLY=($(compgen -W "alphabetical recent current-first" -- "$cur")); return ;;
+        --duration|--request-timeout|--keepalive|--display-column|--as|--as-group|--job|--cronjob) return ;;
+        config) COMPREPLY=($(compgen -W "edit forget" -- "$cur")); return ;;
+        forget) COMPREPLY=($(compgen -W "--all-ports $(kpfr --complete-services 2>/dev/null)" -- "$cur")); return ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "validate config $(kpfr --complete-services 2>/dev/null)" -- "$cur"))
+    fi
+}
+complete -F _kpfr kpfr"#;
+
+pub fn completion_script(shell: &str) -> Result<String> {
+    let flags = completion_flags().join(" ");
+    match shell {
+        "bash" => Ok(BASH_COMPLETION.replace("{flags}", &flags)),
+        _ => Err(CliError::UnsupportedShell(shell.to_owned())),
+    }
+}
+
+/// Long flags of the options table of the usage (not the ones mentioned in descriptions)
+fn completion_flags() -> Vec<&'static str> {
+    let mut flags = USAGE
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| line.starts_with('-'))
+        .filter_map(|line| line.split_whitespace().find(|w| w.starts_with("--")))
+        .map(|flag| flag.trim_end_matches(|c: char| !c.is_ascii_alphanumeric()))
+        .collect::<Vec<_>>();
+    flags.sort();
+    flags.dedup();
+    flags
+}
+
+/// Commands run instead of forwarding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subcommand {
+    /// Check the remembered config against the cluster
+    Validate,
+    /// Open the config in an editor
+    ConfigEdit,
+    /// Remove the remembered ports of a service (of all services if `None`)
+    ConfigForget(Option<String>),
+}
+
+/// kubectl calls which receive the arguments after `--`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PassTo {
+    #[default]
+    Forward,
+    List,
+    All,
+}
+impl FromStr for PassTo {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "forward" => Ok(Self::Forward),
+            "list" => Ok(Self::List),
+            "all" => Ok(Self::All),
+            _ => Err(CliError::InvalidPassTo(s.to_owned())),
+        }
+    }
+}
+
+/// Order of the contexts in the context selection
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContextSort {
+    #[default]
+    Alphabetical,
+    /// Most recently forwarded first
+    Recent,
+    CurrentFirst,
+}
+impl FromStr for ContextSort {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "alphabetical" => Ok(Self::Alphabetical),
+            "recent" => Ok(Self::Recent),
+            "current-first" => Ok(Self::CurrentFirst),
+            _ => Err(CliError::InvalidContextSort(s.to_owned())),
+        }
+    }
+}
+
+/// Format of the config file, given by its extension
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    Toml,
+}
+impl ConfigFormat {
+    pub fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+impl FromStr for ConfigFormat {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            _ => Err(CliError::InvalidConfigFormat(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    Json,
+    /// Aligned columns, e.g. for the forwarded ports
+    Table,
+    Yaml,
+}
+impl FromStr for OutputFormat {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "table" => Ok(Self::Table),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(CliError::InvalidOutputFormat(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Args {
+    pub command: Option<Subcommand>,
+    pub service: Option<String>,
+    pub context: Option<String>,
+    pub namespace: Option<String>,
+    pub set_default_namespace: bool,
+    pub context_glob: Option<String>,
+    pub interactive: bool,
+    pub context_sort: Option<ContextSort>,
+    pub no_context_select: bool,
+    pub context_only: bool,
+    pub ports_file: Option<PathBuf>,
+    pub ports: Option<HashMap<u16, u16>>,
+    pub ports_from_env: Option<String>,
+    pub stdin_ports: bool,
+    pub address: Option<IpAddr>,
+    pub duration: Option<Duration>,
+    pub replace: bool,
+    pub ephemeral: bool,
+    pub reuse: bool,
+    pub describe: bool,
+    pub interactive_ports_only: bool,
+    pub allow_privileged: bool,
+    pub job: Option<String>,
+    pub cronjob: Option<String>,
+    pub use_target_port: bool,
+    pub rotate_pod: bool,
+    pub wait: bool,
+    pub request_timeout: Option<Duration>,
+    pub kubectl_args: Vec<String>,
+    pub pass_to: PassTo,
+    pub impersonate: Option<String>,
+    pub impersonate_groups: Vec<String>,
+    pub insecure_skip_tls_verify: bool,
+    pub no_fuzzy: bool,
+    pub confirm_single_port: bool,
+    pub live_picker: bool,
+    pub select_first: bool,
+    pub strict: bool,
+    pub all_namespaces: bool,
+    pub namespaces: Vec<String>,
+    pub namespace_selector: Option<String>,
+    pub service_type: Option<String>,
+    pub display_column: Option<String>,
+    pub reconnect: bool,
+    pub max_reconnects: Option<u32>,
+    pub health_check: bool,
+    pub keepalive: Option<Duration>,
+    pub logs: bool,
+    pub env_file: Option<PathBuf>,
+    pub session_log: Option<PathBuf>,
+    pub no_color: bool,
+    pub no_hyperlinks: bool,
+    pub show_context: bool,
+    pub suggest_hosts: bool,
+    pub print_ready_json: bool,
+    pub output: OutputFormat,
+    pub quiet: bool,
+    pub verbose: bool,
+    pub print_config_path: bool,
+    pub config_format: Option<ConfigFormat>,
+    pub completions: Option<String>,
+    pub complete_services: bool,
+    pub help: bool,
+    pub version: bool,
+}
+impl Args {
+    pub fn parse() -> Result<Self> {
+        // NOTE: `env::args` panics on arguments which aren't valid UTF-8
+        let args = std::env::args_os()
+            .skip(1)
+            .map(|arg| {
+                arg.into_string()
+                    .map_err(|arg| CliError::InvalidUnicode(arg.to_string_lossy().into_owned()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::parse_from(args)
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Support both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError::MissingValue(flag.clone()))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                "-q" | "--quiet" => parsed.quiet = true,
+                "-v" | "--verbose" => parsed.verbose = true,
+                "--print-ready-json" => parsed.print_ready_json = true,
+                "-o" | "--output" => parsed.output = value()?.parse()?,
+                "--request-timeout" => parsed.request_timeout = Some(parse_duration(&value()?)?),
+                "--" => {
+                    parsed.kubectl_args = args.by_ref().collect();
+                }
+                "--pass-to" => parsed.pass_to = value()?.parse()?,
+                "--as" => parsed.impersonate = Some(non_empty(&flag, value()?)?),
+                "--as-group" => parsed.impersonate_groups.push(non_empty(&flag, value()?)?),
+                "--insecure-skip-tls-verify" => parsed.insecure_skip_tls_verify = true,
+                "--select-first" => parsed.select_first = true,
+                "--strict" => parsed.strict = true,
+                "--no-fuzzy" => parsed.no_fuzzy = true,
+                "--confirm-single-port" => parsed.confirm_single_port = true,
+                "--live-picker" => parsed.live_picker = true,
+                "-A" | "--all-namespaces" | "--namespace-all" => parsed.all_namespaces = true,
+                "--namespaces" => {
+                    parsed.namespaces = value()?
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|n| !n.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                "--namespace-selector" | "--namespace-label" => {
+                    parsed.namespace_selector = Some(value()?)
+                }
+                "--service-type" => parsed.service_type = Some(value()?),
+                "--display-column" => parsed.display_column = Some(value()?),
+                "--reconnect" => parsed.reconnect = true,
+                "--max-reconnects" => {
+                    let max = value()?;
+                    parsed.max_reconnects =
+                        Some(max.parse().map_err(|_| CliError::InvalidNumber(max))?)
+                }
+                "--health-check" => parsed.health_check = true,
+                "--logs" => parsed.logs = true,
+                "--env-file" => parsed.env_file = Some(value()?.into()),
+                "--session-log" => parsed.session_log = Some(value()?.into()),
+                "--no-color" => parsed.no_color = true,
+                "--no-hyperlinks" => parsed.no_hyperlinks = true,
+                "--show-context" => parsed.show_context = true,
+                "--suggest-hosts" => parsed.suggest_hosts = true,
+                "--print-config-path" => parsed.print_config_path = true,
+                "--config-format" => parsed.config_format = Some(value()?.parse()?),
+                "--completions" => parsed.completions = Some(value()?),
+                "--complete-services" => parsed.complete_services = true,
+                "--context" => parsed.context = Some(value()?),
+                "-n" | "--namespace" => parsed.namespace = Some(value()?),
+                "--set-default-namespace" => parsed.set_default_namespace = true,
+                "--context-glob" => parsed.context_glob = Some(value()?),
+                "-i" | "--interactive" => parsed.interactive = true,
+                "--context-sort" => parsed.context_sort = Some(value()?.parse()?),
+                "--no-context-select" => parsed.no_context_select = true,
+                "--context-only" => parsed.context_only = true,
+                "--ports" => parsed.ports = Some(parse_port_mappings(&value()?)?),
+                "--ports-from-env" => parsed.ports_from_env = Some(value()?),
+                "--stdin-ports" => parsed.stdin_ports = true,
+                "--ports-file" => parsed.ports_file = Some(value()?.into()),
+                "--address" => {
+                    let address = value()?;
+                    parsed.address = Some(
+                        address
+                            .parse()
+                            .map_err(|_| CliError::InvalidAddress(address))?,
+                    )
+                }
+                "--allow-privileged" => parsed.allow_privileged = true,
+                "--job" => parsed.job = Some(value()?),
+                "--cronjob" => parsed.cronjob = Some(value()?),
+                "--use-target-port" => parsed.use_target_port = true,
+                "--rotate-pod" => parsed.rotate_pod = true,
+                "--wait" => parsed.wait = true,
+                "--ephemeral" => parsed.ephemeral = true,
+                "--reuse" => parsed.reuse = true,
+                "--describe" => parsed.describe = true,
+                "--interactive-ports-only" => parsed.interactive_ports_only = true,
+                "--replace" => parsed.replace = true,
+                "--keepalive" => parsed.keepalive = Some(positive_duration(&flag, &value()?)?),
+                "--duration" | "--timeout-forward" => {
+                    parsed.duration = Some(positive_duration(&flag, &value()?)?)
+                }
+                "validate" if parsed.command.is_none() && parsed.service.is_none() => {
+                    parsed.command = Some(Subcommand::Validate)
+                }
+                "config" if parsed.command.is_none() && parsed.service.is_none() => {
+                    parsed.command = Some(match value()?.as_str() {
+                        "edit" => Subcommand::ConfigEdit,
+                        "forget" => match value()?.as_str() {
+                            "--all-ports" => Subcommand::ConfigForget(None),
+                            service => Subcommand::ConfigForget(Some(service.to_owned())),
+                        },
+                        other => return Err(CliError::UnknownArgument(format!("config {other}"))),
+                    })
+                }
+                _ if !arg.starts_with('-') && parsed.service.is_none() => {
+                    parsed.service = Some(arg)
+                }
+                _ => return Err(CliError::UnknownArgument(arg)),
+            }
+        }
+        if let Some(target) = parsed.service.take() {
+            parsed.apply_target(&target)?;
+        }
+        let conflict =
+            |a: &str, b: &str| CliError::ConflictingArguments(a.to_owned(), b.to_owned());
+        match (&parsed.job, &parsed.cronjob, &parsed.service) {
+            (Some(_), Some(_), _) => return Err(conflict("--job", "--cronjob")),
+            (Some(_), _, Some(service)) => return Err(conflict("--job", service)),
+            (_, Some(_), Some(service)) => return Err(conflict("--cronjob", service)),
+            _ => {}
+        }
+        if parsed.interactive_ports_only {
+            let prompting = [
+                ("--reuse", parsed.reuse),
+                ("--select-first", parsed.select_first),
+                ("--strict", parsed.strict),
+            ];
+            if let Some((flag, _)) = prompting.iter().find(|(_, set)| *set) {
+                return Err(conflict("--interactive-ports-only", flag));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Splits `[<context>/]<namespace>/<service>[:<ports>]` into its parts, explicit flags win
+    /// over the context and ports of the target, a different `--namespace` conflicts
+    fn apply_target(&mut self, target: &str) -> Result<()> {
+        let invalid = |reason| CliError::InvalidTarget(target.to_owned(), reason);
+        // NOTE: Context names may contain `/` and `:` themselves (e.g. EKS ARNs)
+        let (path, service) = match target.rsplit_once('/') {
+            Some((path, service)) => (Some(path), service),
+            None => (None, target),
+        };
+        let (service, ports) = match service.split_once(':') {
+            Some((service, ports)) => (service, Some(parse_port_mappings(ports)?)),
+            None => (service, None),
+        };
+        let (context, namespace) = match path.map(|p| p.rsplit_once('/').unwrap_or(("", p))) {
+            Some((context, namespace)) => {
+                (Some(context).filter(|c| !c.is_empty()), Some(namespace))
+            }
+            None => (None, None),
+        };
+        if service.is_empty() || namespace.is_some_and(str::is_empty) {
+            return Err(invalid(
+                "expected [<context>/]<namespace>/<service>[:<ports>]",
+            ));
+        }
+        if let (Some(flag), Some(namespace)) = (&self.namespace, namespace)
+            && flag != namespace
+        {
+            return Err(CliError::ConflictingArguments(
+                format!("--namespace {flag}"),
+                target.to_owned(),
+            ));
+        }
+
+        self.service = Some(match namespace {
+            Some(namespace) => format!("{namespace}/{service}"),
+            None => service.to_owned(),
+        });
+        if self.context.is_none() {
+            self.context = context.map(String::from);
+        }
+        if self.ports.is_none() {
+            self.ports = ports;
+        }
+        Ok(())
+    }
+
+    /// Fills what wasn't given on the command line from `KPFR_*` environment variables
+    pub fn with_env(self) -> Result<Self> {
+        self.with_env_from(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+    }
+
+    pub fn with_env_from<F: Fn(&str) -> Option<String>>(mut self, var: F) -> Result<Self> {
+        let namespace_flag = self.namespace.is_some();
+        self.context = self.context.or_else(|| var("KPFR_CONTEXT"));
+        self.namespace = self.namespace.or_else(|| var("KPFR_NAMESPACE"));
+        if self.service.is_none()
+            && let Some(service) = var("KPFR_SERVICE")
+        {
+            // NOTE: `--namespace` wins over the namespace of a `<namespace>/<service>` variable
+            self.service = Some(match service.split_once('/') {
+                Some((_, name)) if namespace_flag => name.to_owned(),
+                _ => service,
+            });
+        }
+        if self.ports.is_none()
+            && let Some(ports) = var("KPFR_PORTS")
+        {
+            self.ports = Some(parse_port_mappings(&ports)?);
+        }
+        Ok(self)
+    }
+}
+
+/// Local port entered in the port prompt, optionally with the address to listen on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalPort {
+    pub address: Option<IpAddr>,
+    pub port: u16,
+}
+impl FromStr for LocalPort {
+    type Err = CliError;
+
+    /// Parses `8080`, `127.0.0.1:8080` or `[::1]:8080`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Ok(port) = s.parse() {
+            return Ok(Self {
+                address: None,
+                port,
+            });
+        }
+        s.parse::<SocketAddr>()
+            .map(|addr| Self {
+                address: Some(addr.ip()),
+                port: addr.port(),
+            })
+            .map_err(|_| CliError::InvalidLocalPort(s.to_owned()))
+    }
+}
+impl Display for LocalPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "{}", SocketAddr::new(address, self.port)),
+            None => write!(f, "{}", self.port),
+        }
+    }
+}
+
+/// Writes a line to stdout
+pub fn write_stdout(line: impl Display) -> io::Result<()> {
+    writeln!(io::stdout().lock(), "{line}")
+}
+
+/// Prints a line to stdout, exiting quietly once the reader went away (e.g. `| head`)
+pub fn print_stdout(line: impl Display) {
+    if let Err(e) = write_stdout(line)
+        && e.kind() == io::ErrorKind::BrokenPipe
+    {
+        process::exit(0);
+    }
+}
+
+/// Parses a port (`80`) or an inclusive port range (`8000-8002`)
+fn parse_port_range(input: &str, mapping: &str) -> Result<Vec<u16>> {
+    let invalid = |reason| CliError::InvalidPortMapping(mapping.to_owned(), reason);
+    let port = |p: &str| {
+        p.trim()
+            .parse::<u16>()
+            .map_err(|_| invalid("expected port numbers"))
+    };
+    match input.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (port(start)?, port(end)?);
+            if start > end {
+                return Err(invalid("descending range"));
+            }
+            Ok((start..=end).collect())
+        }
+        None => Ok(vec![port(input)?]),
+    }
+}
+
+/// Parses comma separated `remote:local` port mappings into a map of remote to local ports.
+/// A single port maps to the same local port, ranges map each port to its counterpart.
+pub fn parse_port_mappings(input: &str) -> Result<HashMap<u16, u16>> {
+    let mut mappings = HashMap::new();
+    for mapping in input.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+        let invalid = |reason| CliError::InvalidPortMapping(mapping.to_owned(), reason);
+        let (remote, local) = match mapping.split_once(':') {
+            Some((remote, local)) => (
+                parse_port_range(remote, mapping)?,
+                parse_port_range(local, mapping)?,
+            ),
+            None => {
+                let ports = parse_port_range(mapping, mapping)?;
+                (ports.clone(), ports)
+            }
+        };
+        if remote.len() != local.len() {
+            return Err(invalid("ranges of different lengths"));
+        }
+        for (remote, local) in remote.into_iter().zip(local) {
+            // NOTE: Local port 0 lets the OS pick a free port, possibly several times
+            if mappings.contains_key(&remote)
+                || (local != 0 && mappings.values().any(|l| *l == local))
+            {
+                return Err(invalid("overlaps another mapping"));
+            }
+            mappings.insert(remote, local);
+        }
+    }
+    if mappings.is_empty() {
+        return Err(CliError::InvalidPortMapping(
+            input.to_owned(),
+            "no ports given",
+        ));
+    }
+    Ok(mappings)
+}
+
+/// Reads port mappings from `<PREFIX>_<REMOTE>=<LOCAL>` environment variables
+pub fn port_mappings_from_env(prefix: &str) -> Result<HashMap<u16, u16>> {
+    port_mappings_from_vars(prefix, std::env::vars_os())
+}
+
+fn port_mappings_from_vars(
+    prefix: &str,
+    vars: impl Iterator<Item = (OsString, OsString)>,
+) -> Result<HashMap<u16, u16>> {
+    let prefix = format!("{prefix}_");
+    // NOTE: Other variables may hold anything, only those with the prefix need to be UTF-8
+    let mut mappings = vars
+        .filter(|(name, _)| name.as_encoded_bytes().starts_with(prefix.as_bytes()))
+        .map(|(name, local)| {
+            let name = name.to_string_lossy().into_owned();
+            let Some(local) = local.to_str() else {
+                return Err(CliError::InvalidPortMapping(name, "not valid UTF-8"));
+            };
+            let remote = name.strip_prefix(&prefix).unwrap_or_default();
+            if remote.parse::<u16>().is_err() || local.trim().parse::<u16>().is_err() {
+                return Err(CliError::InvalidPortMapping(
+                    format!("{name}={local}"),
+                    "expected <PREFIX>_<REMOTE>=<LOCAL>",
+                ));
+            }
+            Ok(format!("{remote}:{local}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if mappings.is_empty() {
+        return Err(CliError::InvalidPortMapping(
+            format!("{prefix}*"),
+            "no variables set",
+        ));
+    }
+    mappings.sort();
+    parse_port_mappings(&mappings.join(","))
+}
+
+/// Reads port mappings line by line (e.g. from stdin), skipping empty lines
+pub fn port_mappings_from_reader<R: BufRead>(reader: R) -> Result<HashMap<u16, u16>> {
+    let mut mappings = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| CliError::UnreadablePorts(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |reason| CliError::InvalidPortMappingLine(i + 1, line.to_owned(), reason);
+        let line_mappings = parse_port_mappings(&line).map_err(|e| match e {
+            CliError::InvalidPortMapping(_, reason) => invalid(reason),
+            _ => invalid("expected remote:local"),
+        })?;
+        for (remote, local) in line_mappings {
+            // NOTE: Local port 0 lets the OS pick a free port, like with `--ports`
+            if mappings.contains_key(&remote)
+                || (local != 0 && mappings.values().any(|l| *l == local))
+            {
+                return Err(invalid("overlaps another mapping"));
+            }
+            mappings.insert(remote, local);
+        }
+    }
+    if mappings.is_empty() {
+        return Err(CliError::InvalidPortMapping(
+            String::from("<stdin>"),
+            "no ports given",
+        ));
+    }
+    Ok(mappings)
+}
+
+/// Rejects blank values of flags which would otherwise be passed on to kubectl as is
+fn non_empty(flag: &str, value: String) -> Result<String> {
+    if value.trim().is_empty() {
+        return Err(CliError::EmptyValue(flag.to_owned()));
+    }
+    Ok(value)
+}
+
+/// Parses human-friendly durations like `90s`, `5m`, `1h` or `1h30m`.
+/// A bare number is interpreted as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let invalid = || CliError::InvalidDuration(input.to_owned());
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(secs) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value = number.parse::<u64>().map_err(|_| invalid())?;
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let secs = value.checked_mul(unit).ok_or_else(invalid)?;
+        total = total
+            .checked_add(Duration::from_secs(secs))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// Parses the duration of a flag which needs one longer than zero
+fn positive_duration(flag: &str, input: &str) -> Result<Duration> {
+    match parse_duration(input)? {
+        Duration::ZERO => Err(CliError::InvalidValue(
+            flag.to_owned(),
+            input.to_owned(),
+            "must be longer than zero",
+        )),
+        duration => Ok(duration),
+    }
+}
+
+/// Formats a duration in the same style accepted by [`parse_duration`]
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    let mut out = String::new();
+    if h > 0 {
+        out.push_str(&format!("{h}h"));
+    }
+    if m > 0 {
+        out.push_str(&format!("{m}m"));
+    }
+    if s > 0 || out.is_empty() {
+        out.push_str(&format!("{s}s"));
+    }
+    out
+}
+
+/// Matches text against a glob pattern supporting `*` (any characters) and `?` (one character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` consume one more character
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse_from(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn namespace_flag_conflicts_with_target_namespace() {
+        assert!(matches!(
+            parse(&["-n", "flag", "web/api"]),
+            Err(CliError::ConflictingArguments(_, target)) if target == "web/api"
+        ));
+        let args = parse(&["-n", "web", "web/api"]).unwrap();
+        assert_eq!(args.service.as_deref(), Some("web/api"));
+        let args = parse(&["-n", "flag", "api"]).unwrap();
+        assert_eq!(args.namespace.as_deref(), Some("flag"));
+    }
+
+    #[test]
+    fn config_format_from_flag_and_extension() {
+        let args = parse(&["--config-format", "toml"]).unwrap();
+        assert_eq!(args.config_format, Some(ConfigFormat::Toml));
+        assert!(matches!(
+            parse(&["--config-format=yaml"]),
+            Err(CliError::InvalidConfigFormat(format)) if format == "yaml"
+        ));
+        assert_eq!(
+            ConfigFormat::of(Path::new("kpfr/config.TOML")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::of(Path::new("kpfr/config.json")),
+            ConfigFormat::Json
+        );
+    }
+
+    #[test]
+    fn completes_only_known_flags() {
+        let flags = completion_flags();
+        assert!(flags.contains(&"--reconnect") && flags.contains(&"--output"));
+        for flag in flags {
+            assert!(
+                !matches!(parse(&[flag]), Err(CliError::UnknownArgument(_))),
+                "{flag}"
+            );
+        }
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (OsString, OsString)> {
+        vars.iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from(value)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn port_mappings_from_prefixed_vars() {
+        let mappings =
+            port_mappings_from_vars("API", vars(&[("API_80", "8080"), ("HOME", "/root")]));
+        assert_eq!(mappings.unwrap(), HashMap::from([(80, 8080)]));
+        assert!(port_mappings_from_vars("API", vars(&[("API_http", "8080")])).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_vars_from_env() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let invalid = OsString::from_vec(vec![0xff, 0xfe]);
+        let unrelated =
+            vars(&[("API_80", "8080")]).chain([(OsString::from("LANG"), invalid.clone())]);
+        assert_eq!(
+            port_mappings_from_vars("API", unrelated).unwrap(),
+            HashMap::from([(80, 8080)])
+        );
+        let prefixed = [(OsString::from("API_80"), invalid)].into_iter();
+        assert!(matches!(
+            port_mappings_from_vars("API", prefixed),
+            Err(CliError::InvalidPortMapping(name, "not valid UTF-8")) if name == "API_80"
+        ));
+    }
+
+    #[test]
+    fn glob_matches_non_ascii_context_names() {
+        assert!(glob_match("prød-*", "prød-zürich"));
+        assert!(glob_match("*-zürich", "prød-zürich"));
+        assert!(glob_match("pr?d-*", "prød-zürich"));
+        assert!(!glob_match("prod-*", "prød-zürich"));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("5x").is_err());
+        assert_eq!(
+            parse(&["--duration", "5m"]).unwrap().duration,
+            Some(Duration::from_secs(300))
+        );
+        for zero in ["0", "0s", "0h0m"] {
+            assert!(matches!(
+                parse(&["--duration", zero]),
+                Err(CliError::InvalidValue(flag, _, _)) if flag == "--duration"
+            ));
+        }
+    }
+
+    #[test]
+    fn zero_keepalive_is_invalid() {
+        assert!(matches!(
+            parse(&["--keepalive", "0s"]),
+            Err(CliError::InvalidValue(flag, value, _)) if flag == "--keepalive" && value == "0s"
+        ));
+        let args = parse(&["--keepalive=30s"]).unwrap();
+        assert_eq!(args.keepalive, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn overflowing_durations_are_invalid() {
+        assert!(matches!(
+            parse_duration("99999999999999999d"),
+            Err(CliError::InvalidDuration(_))
+        ));
+        assert!(matches!(
+            parse_duration(&format!("{}s{}s", u64::MAX, u64::MAX)),
+            Err(CliError::InvalidDuration(_))
+        ));
+    }
+
+    #[test]
+    fn repeated_os_assigned_ports_from_reader() {
+        let mappings = port_mappings_from_reader("80:0\n443:0\n".as_bytes()).unwrap();
+        assert_eq!(mappings, HashMap::from([(80, 0), (443, 0)]));
+    }
+
+    #[test]
+    fn overlapping_ports_from_reader() {
+        assert!(matches!(
+            port_mappings_from_reader("80:8080\n443:8080\n".as_bytes()),
+            Err(CliError::InvalidPortMappingLine(2, _, _))
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+use crate::kubectl::ForwardSpec;
+use crate::summary;
+
+/// Environment variable name part derived from a Kubernetes name (`my-db` → `MY_DB`)
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Variables describing the forward, e.g. `DB_HOST=localhost` and `DB_PORT=5432`.
+/// Services with several ports get one `<SERVICE>_<PORT NAME>_PORT` per port.
+pub fn variables(spec: &ForwardSpec) -> Vec<(String, String)> {
+    let service = env_name(&spec.service.metadata.name);
+    let mut mappings = spec.ports.iter().collect::<Vec<_>>();
+    mappings.sort();
+
+    let mut variables = vec![(format!("{service}_HOST"), summary::host(spec))];
+    if let [(_, local)] = mappings[..] {
+        variables.push((format!("{service}_PORT"), local.to_string()));
+    } else {
+        for (remote, local) in mappings {
+            let port = spec
+                .service
+                .spec
+                .ports
+                .iter()
+                .find(|p| p.port == *remote)
+                .and_then(|p| p.name.as_deref())
+                .map(env_name)
+                .unwrap_or_else(|| remote.to_string());
+            variables.push((format!("{service}_{port}_PORT"), local.to_string()));
+        }
+    }
+    variables
+}
+
+/// Sets the variables in the dotenv file, keeping all other lines as they are
+pub fn write<P: AsRef<Path>>(filename: &P, variables: &[(String, String)]) -> Result<()> {
+    let existing = match fs::read_to_string(filename) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines = existing
+        .lines()
+        .filter(|line| {
+            let key = line.split_once('=').map(|(key, _)| key.trim());
+            !key.is_some_and(|key| variables.iter().any(|(name, _)| name == key))
+        })
+        .map(String::from)
+        .collect::<Vec<_>>();
+    lines.extend(
+        variables
+            .iter()
+            .map(|(name, value)| format!("{name}={value}")),
+    );
+    fs::write(filename, lines.join("\n") + "\n")
+}
@@ -1,22 +1,120 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum MainError {
+    #[error("Could not determine the config directory (is $HOME set?)")]
+    NoConfigDir,
+
     #[error("No context found")]
     NoContext,
 
+    #[error("Context '{0}' not found")]
+    ContextNotFound(String),
+
+    #[error("Could not switch to context '{0}': {1}")]
+    ContextSwitchFailed(String, KubectlError),
+
+    #[error("No context found matching '{0}'")]
+    NoContextMatching(String),
+
+    #[error("Several contexts match '{0}' (use -i to pick one): {candidates}", candidates = .1.join(", "))]
+    AmbiguousContext(String, Vec<String>),
+
+    #[error("Not allowed to list {0}{scope} (check your RBAC permissions)", scope = .1.as_ref().map(|ns| format!(" in namespace '{ns}'")).unwrap_or_default())]
+    PermissionDenied(String, Option<String>),
+
     #[error("No namespace found")]
     NoNamespace,
 
+    #[error("No namespace found matching selector '{0}'")]
+    NoNamespaceMatching(String),
+
     #[error("No service found in namespace '{0}'")]
     NoService(String),
 
+    #[error("No service of type '{0}' found in namespace '{1}'")]
+    NoServiceOfType(String, String),
+
+    #[error("Service '{0}' not found in namespace '{1}'")]
+    ServiceNotFound(String, String),
+
+    #[error("Service '{0}' is ambiguous, candidates: {candidates}", candidates = .1.join(", "))]
+    AmbiguousService(String, Vec<String>),
+
+    #[error("No pod found for job '{0}' in namespace '{1}'")]
+    NoJobPod(String, String),
+
+    #[error("No job of cronjob '{0}' found in namespace '{1}'")]
+    NoCronJobRun(String, String),
+
+    #[error("Port {0} is not exposed by service '{1}'")]
+    UnknownPort(u16, String),
+
+    #[error("Could not read ports file {path}: {1}", path = .0.display())]
+    UnreadablePortsFile(PathBuf, std::io::Error),
+
+    #[error("Invalid ports file {path} (expected {{\"service\": {{\"remote\": local}}}}): {1}", path = .0.display())]
+    InvalidPortsFile(PathBuf, serde_json::Error),
+
+    #[error("Invalid address '{0}' (expected an IP address)")]
+    InvalidAddress(String),
+
+    #[error(
+        "Conflicting local addresses {0} and {1} (kubectl listens on the same addresses for all ports)"
+    )]
+    ConflictingAddresses(IpAddr, IpAddr),
+
+    #[error("Forwarding against protected context '{0}' was not confirmed")]
+    NotConfirmed(String),
+
+    #[error("Forwarding the system service '{0}' was not confirmed")]
+    ServiceNotConfirmed(String),
+
+    #[error("Found {0} stale entries in the remembered config")]
+    StaleConfig(usize),
+
+    #[error("Invalid config {path} (fix it with `kpfr config edit`): {1}", path = .0.display())]
+    InvalidConfig(PathBuf, serde_json::Error),
+
+    #[error("Editor '{0}' exited with an error")]
+    EditorFailed(String),
+
+    #[error("Cluster of context '{0}' is unreachable (VPN down or cluster asleep?): {1}")]
+    ClusterUnreachable(String, String),
+
+    #[error("Refusing to prompt in strict mode ({0}), candidates: {candidates}", candidates = .1.join(", "))]
+    PromptRequired(String, Vec<String>),
+
+    #[error("No ports remembered for '{0}'")]
+    NotRemembered(String),
+
     #[error("No ports selected")]
     NoPorts,
 
+    #[error("Pod '{1}' has no container port named '{0}'")]
+    UnknownPortName(String, String),
+
+    #[error("Service '{0}' exposes no ports")]
+    NoServicePorts(String),
+
+    #[error("Cannot forward {ports} of service '{0}', kubectl port-forward only supports TCP", ports = .1.join(", "))]
+    UnsupportedProtocol(String, Vec<String>),
+
+    #[error("Port-forward exited unexpectedly (last error: {last})", last = .0.as_deref().unwrap_or("unknown"))]
+    ForwardExited(Option<String>),
+
+    #[error("Giving up after {0} reconnect attempts (last error: {last})", last = .1.as_deref().unwrap_or("unknown"))]
+    ReconnectsExhausted(u32, Option<String>),
+
     #[error("No valid selection")]
     InvalidSelection(#[from] dialoguer::Error),
 
+    #[error(transparent)]
+    InvalidArguments(#[from] CliError),
+
     #[error(transparent)]
     KubectlFailed(#[from] KubectlError),
 
@@ -27,6 +125,135 @@ pub enum MainError {
     CtrlC(#[from] ctrlc::Error),
 }
 
+impl MainError {
+    /// Stable identifier of the error kind, used in machine-readable output
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoConfigDir => "NoConfigDir",
+            Self::NoContext => "NoContext",
+            Self::ContextNotFound(_) => "ContextNotFound",
+            Self::ContextSwitchFailed(_, _) => "ContextSwitchFailed",
+            Self::NoContextMatching(_) => "NoContextMatching",
+            Self::AmbiguousContext(_, _) => "AmbiguousContext",
+            Self::PermissionDenied(_, _) => "PermissionDenied",
+            Self::NoNamespace => "NoNamespace",
+            Self::NoNamespaceMatching(_) => "NoNamespaceMatching",
+            Self::NoService(_) => "NoService",
+            Self::NoServiceOfType(_, _) => "NoServiceOfType",
+            Self::ServiceNotFound(_, _) => "ServiceNotFound",
+            Self::AmbiguousService(_, _) => "AmbiguousService",
+            Self::NoJobPod(_, _) => "NoJobPod",
+            Self::NoCronJobRun(_, _) => "NoCronJobRun",
+            Self::UnknownPort(_, _) => "UnknownPort",
+            Self::UnreadablePortsFile(_, _) => "UnreadablePortsFile",
+            Self::InvalidPortsFile(_, _) => "InvalidPortsFile",
+            Self::InvalidAddress(_) => "InvalidAddress",
+            Self::ConflictingAddresses(_, _) => "ConflictingAddresses",
+            Self::NotConfirmed(_) => "NotConfirmed",
+            Self::ServiceNotConfirmed(_) => "ServiceNotConfirmed",
+            Self::StaleConfig(_) => "StaleConfig",
+            Self::InvalidConfig(_, _) => "InvalidConfig",
+            Self::EditorFailed(_) => "EditorFailed",
+            Self::ClusterUnreachable(_, _) => "ClusterUnreachable",
+            Self::PromptRequired(_, _) => "PromptRequired",
+            Self::NotRemembered(_) => "NotRemembered",
+            Self::NoPorts => "NoPorts",
+            Self::NoServicePorts(_) => "NoServicePorts",
+            Self::UnsupportedProtocol(_, _) => "UnsupportedProtocol",
+            Self::UnknownPortName(_, _) => "UnknownPortName",
+            Self::ForwardExited(_) => "ForwardExited",
+            Self::ReconnectsExhausted(_, _) => "ReconnectsExhausted",
+            Self::InvalidSelection(_) => "InvalidSelection",
+            Self::InvalidArguments(_) => "InvalidArguments",
+            Self::KubectlFailed(_) => "KubectlFailed",
+            Self::IOError(_) => "IOError",
+            Self::CtrlC(_) => "CtrlC",
+        }
+    }
+
+    /// Whether the user aborted a prompt (Ctrl-C), which is no error to report
+    pub fn is_cancelled(&self) -> bool {
+        match self {
+            Self::InvalidSelection(dialoguer::Error::IO(e)) => {
+                e.kind() == std::io::ErrorKind::Interrupted
+            }
+            _ => false,
+        }
+    }
+
+    /// Machine-readable representation including the relevant fields of the error
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "error": self.code(),
+            "message": self.to_string(),
+        });
+        match self {
+            Self::ContextNotFound(context) | Self::NotConfirmed(context) => {
+                value["context"] = context.as_str().into()
+            }
+            Self::ContextSwitchFailed(context, e) => {
+                value["context"] = context.as_str().into();
+                value["detail"] = e.to_string().into();
+            }
+            Self::ClusterUnreachable(context, detail) => {
+                value["context"] = context.as_str().into();
+                value["detail"] = detail.as_str().into();
+            }
+            Self::NoContextMatching(pattern) => value["pattern"] = pattern.as_str().into(),
+            Self::AmbiguousContext(pattern, candidates) => {
+                value["pattern"] = pattern.as_str().into();
+                value["candidates"] = candidates.clone().into();
+            }
+            Self::PermissionDenied(resource, namespace) => {
+                value["resource"] = resource.as_str().into();
+                if let Some(namespace) = namespace {
+                    value["namespace"] = namespace.as_str().into();
+                }
+            }
+            Self::NoNamespaceMatching(selector) => value["selector"] = selector.as_str().into(),
+            Self::NotRemembered(service)
+            | Self::NoServicePorts(service)
+            | Self::ServiceNotConfirmed(service) => value["service"] = service.as_str().into(),
+            Self::NoService(namespace) => value["namespace"] = namespace.as_str().into(),
+            Self::NoServiceOfType(service_type, namespace) => {
+                value["type"] = service_type.as_str().into();
+                value["namespace"] = namespace.as_str().into();
+            }
+            Self::ServiceNotFound(service, namespace) => {
+                value["service"] = service.as_str().into();
+                value["namespace"] = namespace.as_str().into();
+            }
+            Self::NoJobPod(job, namespace) => {
+                value["job"] = job.as_str().into();
+                value["namespace"] = namespace.as_str().into();
+            }
+            Self::NoCronJobRun(cronjob, namespace) => {
+                value["cronjob"] = cronjob.as_str().into();
+                value["namespace"] = namespace.as_str().into();
+            }
+            Self::AmbiguousService(service, candidates) => {
+                value["service"] = service.as_str().into();
+                value["candidates"] = candidates.clone().into();
+            }
+            Self::UnsupportedProtocol(service, ports) => {
+                value["service"] = service.as_str().into();
+                value["ports"] = ports.clone().into();
+            }
+            Self::PromptRequired(prompt, candidates) => {
+                value["prompt"] = prompt.as_str().into();
+                value["candidates"] = candidates.clone().into();
+            }
+            Self::UnknownPortName(name, pod) => {
+                value["port"] = name.as_str().into();
+                value["pod"] = pod.as_str().into();
+            }
+            Self::InvalidConfig(path, _) => value["path"] = path.display().to_string().into(),
+            _ => {}
+        }
+        value
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum KubectlError {
     #[error(transparent)]
@@ -35,9 +262,78 @@ pub enum KubectlError {
     #[error("Command failed")]
     CommandFailed,
 
+    #[error("{0}")]
+    Failed(String),
+
+    #[error("{0}")]
+    Unreachable(String),
+
+    #[error("Invalid name '{0}' (expected lowercase letters, digits and '-')")]
+    InvalidName(String),
+
     #[error(transparent)]
     ParseOutput(#[from] std::string::FromUtf8Error),
 
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
 }
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("Argument '{0}' is not valid UTF-8")]
+    InvalidUnicode(String),
+
+    #[error("Unknown argument '{0}'")]
+    UnknownArgument(String),
+
+    #[error("Missing value for '{0}'")]
+    MissingValue(String),
+
+    #[error("Empty value for '{0}'")]
+    EmptyValue(String),
+
+    #[error("Unsupported shell '{0}' (supported: bash)")]
+    UnsupportedShell(String),
+
+    #[error("Invalid context order '{0}' (expected alphabetical, recent or current-first)")]
+    InvalidContextSort(String),
+
+    #[error("Invalid config format '{0}' (expected json or toml)")]
+    InvalidConfigFormat(String),
+
+    #[error("Invalid output format '{0}' (expected human, json, table or yaml)")]
+    InvalidOutputFormat(String),
+
+    #[error("Invalid address '{0}' (expected an IP address)")]
+    InvalidAddress(String),
+
+    #[error("Invalid port mapping '{0}' ({1})")]
+    InvalidPortMapping(String, &'static str),
+
+    #[error("Invalid port mapping '{1}' on line {0} ({2})")]
+    InvalidPortMappingLine(usize, String, &'static str),
+
+    #[error("Could not read port mappings: {0}")]
+    UnreadablePorts(String),
+
+    #[error("Invalid value '{0}' for --pass-to (expected forward, list or all)")]
+    InvalidPassTo(String),
+
+    #[error("Invalid local port '{0}' (expected e.g. 8080 or 127.0.0.1:8080)")]
+    InvalidLocalPort(String),
+
+    #[error("Invalid target '{0}' ({1})")]
+    InvalidTarget(String, &'static str),
+
+    #[error("Invalid number '{0}'")]
+    InvalidNumber(String),
+
+    #[error("Invalid duration '{0}' (expected e.g. 90s, 5m, 1h)")]
+    InvalidDuration(String),
+
+    #[error("Invalid value '{1}' for '{0}' ({2})")]
+    InvalidValue(String, String, &'static str),
+
+    #[error("'{0}' can't be combined with '{1}'")]
+    ConflictingArguments(String, String),
+}
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::process::{Child, ExitStatus};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{cmp, thread};
+
+use indicatif::ProgressBar;
+
+use crate::cli;
+use crate::error::KubectlError;
+use crate::kubectl::{self, ForwardSpec, KubectlConfig};
+use crate::model::Port;
+
+type Result<T> = std::result::Result<T, KubectlError>;
+
+/// How often the forwarded local ports get probed
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Consecutive failed probes after which the forward is restarted
+pub const HEALTH_CHECK_MAX_FAILURES: u32 = 3;
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a probe waits for kubectl to drop the connection of a broken tunnel
+const HEALTH_CHECK_DROP_WAIT: Duration = Duration::from_millis(500);
+/// Reconnect attempts used when only `--reconnect` is given
+pub const DEFAULT_MAX_RECONNECTS: u32 = 5;
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// How long to wait for kubectl to report OS-assigned local ports
+pub const BIND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A running `kubectl port-forward` together with everything needed to restart it
+pub struct ForwardHandle {
+    config: KubectlConfig,
+    spec: ForwardSpec,
+    child: Child,
+    output: ProgressBar,
+    /// Pass kubectl's stdout to stderr, keeping stdout for kpfr's own output
+    stdout_to_stderr: bool,
+    last_error: Arc<Mutex<Option<String>>>,
+    /// Local and target port of every "Forwarding from" line kubectl printed
+    listening: Arc<Mutex<Vec<(u16, u16)>>>,
+    pub started: Instant,
+    pub reconnects: u32,
+    /// Forward to the next ready pod on every restart (only when forwarding to a pod)
+    pub rotate_pods: bool,
+}
+impl ForwardHandle {
+    pub fn spawn(
+        config: &KubectlConfig,
+        spec: ForwardSpec,
+        output: &ProgressBar,
+        stdout_to_stderr: bool,
+    ) -> Result<Self> {
+        let last_error = Arc::new(Mutex::new(None));
+        let listening = Arc::new(Mutex::new(Vec::new()));
+        let child = spawn_child(
+            config,
+            &spec,
+            output,
+            stdout_to_stderr,
+            &last_error,
+            &listening,
+        )?;
+        Ok(Self {
+            config: config.to_owned(),
+            spec,
+            child,
+            output: output.to_owned(),
+            stdout_to_stderr,
+            last_error,
+            listening,
+            started: Instant::now(),
+            reconnects: 0,
+            rotate_pods: false,
+        })
+    }
+
+    /// Returns the exit status if the kubectl process is gone
+    pub fn exited(&mut self) -> Result<Option<ExitStatus>> {
+        Ok(self.child.try_wait()?)
+    }
+
+    /// Last line kubectl wrote to stderr
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().ok()?.clone()
+    }
+
+    /// Remote to local ports once kubectl listens on all of them, resolving
+    /// OS-assigned local ports (`0`)
+    pub fn bound_ports(&self) -> Option<HashMap<u16, u16>> {
+        let listening = self.listening.lock().ok()?.clone();
+        let configured = self.spec.ports.values().collect::<Vec<_>>();
+        let mut assigned = listening
+            .iter()
+            .filter(|(local, _)| !configured.contains(&local))
+            .collect::<Vec<_>>();
+        let mut bound = HashMap::new();
+        for (remote, local) in &self.spec.ports {
+            let port = if *local != 0 {
+                listening.iter().any(|(l, _)| l == local).then_some(*local)
+            } else {
+                // NOTE: kubectl reports the target port, which matches the remote one of pods
+                // and of services with numeric target ports
+                let target = self
+                    .spec
+                    .service
+                    .spec
+                    .ports
+                    .iter()
+                    .find(|p| p.port == *remote)
+                    .and_then(Port::numeric_target_port)
+                    .unwrap_or(*remote);
+                let index = assigned
+                    .iter()
+                    .position(|(_, t)| *t == target)
+                    .or((assigned.len() == 1).then_some(0));
+                index.map(|i| assigned.remove(i).0)
+            };
+            bound.insert(*remote, port?);
+        }
+        Some(bound)
+    }
+
+    /// Waits until kubectl listens on all ports, `None` if it exits or times out first
+    pub fn wait_bound(&mut self, timeout: Duration) -> Option<HashMap<u16, u16>> {
+        let started = Instant::now();
+        loop {
+            if let Some(ports) = self.bound_ports() {
+                return Some(ports);
+            }
+            if started.elapsed() >= timeout || !matches!(self.child.try_wait(), Ok(None)) {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Service key (`namespace/service`) of the forwarded service
+    pub fn service_key(&self) -> String {
+        self.spec.service.key()
+    }
+
+    /// Single JSON line describing where the ready forward listens
+    pub fn ready_json(&self, ports: &HashMap<u16, u16>) -> serde_json::Value {
+        let address = match self.spec.address {
+            Some(ip) if !ip.is_unspecified() => ip,
+            _ => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        serde_json::json!({
+            "namespace": self.spec.namespace.metadata.name,
+            "service": self.spec.service.metadata.name,
+            "target": self.spec.target(),
+            "address": address.to_string(),
+            "ports": ports,
+        })
+    }
+
+    /// Compact single-line description of the running forward
+    pub fn status_line(&self) -> String {
+        format!(
+            "forwarding {} ({} port{}) • up {} • reconnects: {}",
+            self.spec.service,
+            self.spec.ports.len(),
+            if self.spec.ports.len() == 1 { "" } else { "s" },
+            cli::format_duration(self.started.elapsed()),
+            self.reconnects
+        )
+    }
+
+    /// Local addresses the forward is listening on, none while kubectl isn't listening yet
+    pub fn local_addresses(&self) -> Vec<SocketAddr> {
+        let ip = match self.spec.address {
+            Some(ip) if !ip.is_unspecified() => ip,
+            _ => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        self.bound_ports()
+            .unwrap_or_default()
+            .values()
+            .map(|port| SocketAddr::new(ip, *port))
+            .collect()
+    }
+
+    /// Kills the current kubectl process and spawns a new one with the same mappings
+    pub fn restart(&mut self) -> Result<()> {
+        self.stop()?;
+        // NOTE: Keep the current pod if the endpoints can't be looked up
+        if self.rotate_pods
+            && let Ok(Some(pod)) = self.next_pod()
+        {
+            self.output
+                .suspend(|| eprintln!("Rotating the forward to pod {pod}"));
+            self.spec.pod = Some(pod);
+        }
+        if let Ok(mut listening) = self.listening.lock() {
+            listening.clear();
+        }
+        self.child = spawn_child(
+            &self.config,
+            &self.spec,
+            &self.output,
+            self.stdout_to_stderr,
+            &self.last_error,
+            &self.listening,
+        )?;
+        self.reconnects += 1;
+        Ok(())
+    }
+
+    /// Ready pod following the current one (round-robin), `None` when not forwarding to a pod
+    fn next_pod(&self) -> Result<Option<String>> {
+        let Some(current) = &self.spec.pod else {
+            return Ok(None);
+        };
+        let namespace = self
+            .spec
+            .service
+            .namespace()
+            .unwrap_or_else(|| self.spec.namespace.to_owned());
+        let mut pods = kubectl::service::endpoints(
+            &self.config,
+            &namespace.metadata.name,
+            &self.spec.service.metadata.name,
+        )?
+        .ready_pods();
+        pods.sort();
+        pods.dedup();
+        Ok(pods
+            .iter()
+            .find(|pod| *pod > current)
+            .or(pods.first())
+            .cloned())
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        // NOTE: The process might already be gone (e.g. it received the SIGINT as well)
+        let _ = self.child.kill();
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+/// A `kubectl logs --follow` of the pods behind a forwarded service
+pub struct LogTail {
+    child: Child,
+}
+impl LogTail {
+    /// Starts following the logs, `None` if the service has no selector to find its pods by
+    pub fn spawn(
+        config: &KubectlConfig,
+        spec: &ForwardSpec,
+        output: &ProgressBar,
+        stdout_to_stderr: bool,
+    ) -> Result<Option<Self>> {
+        let Some(selector) = spec
+            .service
+            .spec
+            .selector
+            .as_ref()
+            .filter(|s| !s.is_empty())
+        else {
+            return Ok(None);
+        };
+        let selector = selector
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let namespace = spec
+            .service
+            .namespace()
+            .unwrap_or_else(|| spec.namespace.to_owned());
+        let mut child = kubectl::tail_logs(config, &namespace, &selector)?;
+        if let Some(stdout) = child.stdout.take() {
+            pass_through(stdout, output.to_owned(), stdout_to_stderr, |_| {});
+        }
+        if let Some(stderr) = child.stderr.take() {
+            pass_through(stderr, output.to_owned(), true, |_| {});
+        }
+        Ok(Some(Self { child }))
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        let _ = self.child.kill();
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+/// Spawns kubectl, passing its output through while remembering the last error line
+fn spawn_child(
+    config: &KubectlConfig,
+    spec: &ForwardSpec,
+    output: &ProgressBar,
+    stdout_to_stderr: bool,
+    last_error: &Arc<Mutex<Option<String>>>,
+    listening: &Arc<Mutex<Vec<(u16, u16)>>>,
+) -> Result<Child> {
+    let mut child = kubectl::forward_ports(config, spec)?;
+    if let Some(stdout) = child.stdout.take() {
+        let listening = Arc::clone(listening);
+        pass_through(stdout, output.to_owned(), stdout_to_stderr, move |line| {
+            if let Some(ports) = parse_listening(&line)
+                && let Ok(mut listening) = listening.lock()
+                && !listening.contains(&ports)
+            {
+                listening.push(ports);
+            }
+        });
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let last_error = Arc::clone(last_error);
+        pass_through(stderr, output.to_owned(), true, move |line| {
+            if let Ok(mut last) = last_error.lock() {
+                *last = Some(line);
+            }
+        });
+    }
+    Ok(child)
+}
+
+/// Local and target port of kubectl's `Forwarding from 127.0.0.1:8080 -> 80`
+fn parse_listening(line: &str) -> Option<(u16, u16)> {
+    let (from, target) = line.strip_prefix("Forwarding from ")?.split_once(" -> ")?;
+    let (_, local) = from.rsplit_once(':')?;
+    Some((local.parse().ok()?, target.trim().parse().ok()?))
+}
+
+/// Prints the lines of the reader without garbling the status line
+fn pass_through<R: Read + Send + 'static>(
+    reader: R,
+    output: ProgressBar,
+    to_stderr: bool,
+    on_line: impl Fn(String) + Send + 'static,
+) {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(|l| l.ok()) {
+            output.suspend(|| {
+                if to_stderr {
+                    eprintln!("{line}")
+                } else {
+                    // NOTE: Keep forwarding even if nobody reads the output anymore
+                    let _ = cli::write_stdout(&line);
+                }
+            });
+            on_line(line);
+        }
+    });
+}
+
+/// Delay before the given reconnect attempt, doubling up to a maximum
+pub fn reconnect_backoff(attempt: u32) -> Duration {
+    let backoff = Duration::from_secs(1) * 2u32.saturating_pow(attempt.saturating_sub(1));
+    cmp::min(backoff, RECONNECT_BACKOFF_MAX)
+}
+
+/// Periodically probes the forwarded local ports to detect half-broken tunnels
+pub struct HealthCheck {
+    last_check: Instant,
+    pub failures: u32,
+    pub healthy: bool,
+}
+impl HealthCheck {
+    pub fn new() -> Self {
+        Self {
+            last_check: Instant::now(),
+            failures: 0,
+            healthy: true,
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last_check.elapsed() >= HEALTH_CHECK_INTERVAL
+    }
+
+    /// Probes all ports and returns the new state if it changed
+    pub fn check(&mut self, addresses: &[SocketAddr]) -> Option<bool> {
+        self.last_check = Instant::now();
+        let healthy = addresses.iter().all(probe_tunnel);
+        if healthy {
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+        }
+        let changed = healthy != self.healthy;
+        self.healthy = healthy;
+        changed.then_some(healthy)
+    }
+
+    pub fn reset(&mut self) {
+        self.last_check = Instant::now();
+        self.failures = 0;
+    }
+}
+
+/// Periodically opens a short-lived connection through every forwarded port,
+/// so intermediaries don't close the idle tunnel
+pub struct Keepalive {
+    interval: Duration,
+    last_ping: HashMap<SocketAddr, Instant>,
+}
+impl Keepalive {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_ping: HashMap::new(),
+        }
+    }
+
+    /// Pings the addresses whose interval elapsed, returning those which failed
+    pub fn ping_due(&mut self, addresses: &[SocketAddr]) -> Vec<SocketAddr> {
+        let now = Instant::now();
+        let mut failed = Vec::new();
+        for address in addresses {
+            // NOTE: The first ping is due one interval after the forward started
+            let last = *self.last_ping.entry(*address).or_insert(now);
+            if now.duration_since(last) < self.interval {
+                continue;
+            }
+            if !probe(address) {
+                failed.push(*address);
+            }
+            self.last_ping.insert(*address, Instant::now());
+        }
+        failed
+    }
+}
+
+/// Checks whether a TCP connection to the local address can be established
+pub fn probe(address: &SocketAddr) -> bool {
+    TcpStream::connect_timeout(address, HEALTH_CHECK_TIMEOUT).is_ok()
+}
+
+/// Checks whether a connection through the tunnel stays open. kubectl accepts connections
+/// even if it can't reach the pod anymore, but then closes them right away.
+fn probe_tunnel(address: &SocketAddr) -> bool {
+    let Ok(mut stream) = TcpStream::connect_timeout(address, HEALTH_CHECK_TIMEOUT) else {
+        return false;
+    };
+    if stream
+        .set_read_timeout(Some(HEALTH_CHECK_DROP_WAIT))
+        .is_err()
+    {
+        return true;
+    }
+    // NOTE: Servers talking first (e.g. SSH) send data, others wait for a request
+    match stream.read(&mut [0; 1]) {
+        Ok(0) => false,
+        Ok(_) => true,
+        Err(e) => matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn open_connection_is_healthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+        assert!(probe_tunnel(&address));
+        drop(server.join());
+    }
+
+    #[test]
+    fn dropped_connection_is_unhealthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || drop(listener.accept()));
+        assert!(!probe_tunnel(&address));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn closed_port_is_unhealthy() {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        assert!(!probe_tunnel(&address));
+    }
+}
@@ -1,24 +1,182 @@
-use std::collections::HashMap;
-use std::process::{Child, Command};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::hash::Hash;
+use std::io::Read;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
 
 use crate::error::KubectlError;
-use crate::model::{Namespace, Service};
+use crate::model::{KubectlList, Namespace, Service, TargetPort};
 
 const KUBECTL: &str = "kubectl";
 
+/// kubectl as found on the PATH at the first call, so the listings and the port-forward
+/// always run the same binary (falling back to the bare name, failing with `NotFound`)
+pub fn binary() -> &'static Path {
+    static BINARY: OnceLock<PathBuf> = OnceLock::new();
+    BINARY.get_or_init(|| {
+        let name = format!("{KUBECTL}{}", env::consts::EXE_SUFFIX);
+        env::var_os("PATH")
+            .and_then(|paths| {
+                env::split_paths(&paths)
+                    .map(|dir| dir.join(&name))
+                    .find(|path| path.is_file())
+            })
+            .unwrap_or_else(|| PathBuf::from(KUBECTL))
+    })
+}
+
+/// Creates a kubectl command using the resolved binary and the kubeconfig kpfr started with
+fn kubectl() -> Command {
+    static KUBECONFIG: OnceLock<Option<OsString>> = OnceLock::new();
+    let mut command = Command::new(binary());
+    if let Some(kubeconfig) = KUBECONFIG.get_or_init(|| env::var_os("KUBECONFIG")) {
+        command.env("KUBECONFIG", kubeconfig);
+    }
+    command
+}
+
 type Result<T> = std::result::Result<T, KubectlError>;
 
-pub mod context {
-    use std::process::Command;
+/// Reports what a long running kubectl call is waiting for
+pub type Progress<'a> = Option<&'a dyn Fn(&str)>;
 
-    use super::*;
+/// Time after which a kubectl call is considered slow (e.g. waiting for VPN or auth)
+const SLOW_CALL: Duration = Duration::from_secs(5);
+
+/// Options applied to the kubectl invocations
+#[derive(Debug, Default, Clone)]
+pub struct KubectlConfig {
+    /// Context every call targets explicitly, so switching the current context
+    /// elsewhere can't redirect a running forward
+    pub context: Option<String>,
+    /// User to impersonate (kubectl's `--as`, RBAC must permit impersonating it)
+    pub impersonate: Option<String>,
+    /// Groups to impersonate (kubectl's `--as-group`)
+    pub impersonate_groups: Vec<String>,
+    /// Skip the server certificate verification (dangerous, dev clusters only)
+    pub insecure_skip_tls_verify: bool,
+    /// Timeout of the API requests of the listing commands (kubectl's `--request-timeout`)
+    pub request_timeout: Option<Duration>,
+    /// Extra arguments passed verbatim to the listing commands
+    pub list_args: Vec<String>,
+    /// Extra arguments passed verbatim to `kubectl port-forward`
+    pub forward_args: Vec<String>,
+}
+impl KubectlConfig {
+    /// Creates a kubectl command with all configured global flags applied
+    fn command(&self) -> Command {
+        let mut command = kubectl();
+        if let Some(context) = &self.context {
+            command.arg(format!("--context={context}"));
+        }
+        if let Some(user) = &self.impersonate {
+            command.arg(format!("--as={user}"));
+        }
+        for group in &self.impersonate_groups {
+            command.arg(format!("--as-group={group}"));
+        }
+        if self.insecure_skip_tls_verify {
+            command.arg("--insecure-skip-tls-verify=true");
+        }
+        command
+    }
+
+    /// Creates a kubectl command for a single API request, which may time out
+    fn request_command(&self) -> Command {
+        let mut command = self.command();
+        if let Some(timeout) = self.request_timeout {
+            command.arg(format!("--request-timeout={}s", timeout.as_secs().max(1)));
+        }
+        command.args(&self.list_args);
+        command
+    }
+}
+
+/// Runs the command like `Command::output`, reporting through the progress while it takes long
+fn output(command: &mut Command, progress: Progress) -> Result<Output> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // NOTE: Read both pipes in the background, so kubectl can't block on a full pipe
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let started = Instant::now();
+    let mut reported = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if !reported && started.elapsed() >= SLOW_CALL {
+            if let Some(progress) = progress {
+                progress("still waiting, check your VPN/auth...");
+            }
+            reported = true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
 
-    const KUBECTL: &str = "kubectl";
+/// Parses kubectl's JSON output, which is a `List` or a single object when fetching by name
+fn parse_list_or_single<T: DeserializeOwned>(output: &str) -> Result<Vec<T>> {
+    let value = serde_json::from_str::<serde_json::Value>(output)?;
+    if value.get("items").is_some() {
+        Ok(serde_json::from_value::<KubectlList<T>>(value)?.items)
+    } else {
+        Ok(vec![serde_json::from_value(value)?])
+    }
+}
+
+/// Parses kubectl's services output, keeping each raw service next to the parsed one
+fn parse_services(output: &str) -> Result<Vec<Service>> {
+    parse_list_or_single::<serde_json::Value>(output)?
+        .into_iter()
+        .map(|raw| {
+            let mut service = serde_json::from_value::<Service>(raw.clone())?;
+            service.raw = raw;
+            Ok(service)
+        })
+        .collect()
+}
+
+/// Removes duplicate entries (e.g. from merged kubeconfigs) while keeping the original order
+fn dedup<T: Eq + Hash + Clone>(items: Vec<T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|item| seen.insert(item.clone()))
+        .collect()
+}
+
+pub mod context {
+    use super::*;
+    use crate::model::{ContextInfo, KubeConfig};
 
     pub fn current() -> Result<String> {
-        let output = Command::new(KUBECTL)
-            .args(["config", "current-context"])
-            .output()?;
+        let output = kubectl().args(["config", "current-context"]).output()?;
         if !output.status.success() {
             return Err(KubectlError::CommandFailed);
         }
@@ -27,42 +185,252 @@ pub mod context {
     }
 
     pub fn get() -> Result<Vec<String>> {
-        let output = Command::new(KUBECTL)
+        let output = kubectl()
             .args(["config", "get-contexts", "--output=name"])
             .output()?;
         if !output.status.success() {
             return Err(KubectlError::CommandFailed);
         }
-        Ok(String::from_utf8(output.stdout)?
-            .trim()
+        Ok(parse_names(&String::from_utf8(output.stdout)?))
+    }
+
+    /// Context names of the `--output=name` listing
+    fn parse_names(output: &str) -> Vec<String> {
+        // NOTE: Trim every line to get rid of `\r` and trailing spaces on some platforms
+        let mut seen = HashSet::new();
+        output
             .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && seen.insert(*line))
             .map(String::from)
-            .collect::<Vec<_>>())
+            .collect()
     }
 
-    pub fn set(context: &str) -> Result<()> {
-        let output = Command::new(KUBECTL)
-            .args(["config", "use-context", context])
+    /// Contexts whose names only differ in case, as merged kubeconfigs may contain
+    pub fn duplicates(contexts: &[String]) -> Vec<&str> {
+        let mut names = HashMap::<String, usize>::new();
+        for context in contexts {
+            *names.entry(context.to_lowercase()).or_default() += 1;
+        }
+        contexts
+            .iter()
+            .filter(|c| names[&c.to_lowercase()] > 1)
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Server URL of the cluster of every context in the (merged) kubeconfig
+    pub fn servers() -> Result<HashMap<String, String>> {
+        let output = kubectl()
+            .args(["config", "view", "--output=json"])
             .output()?;
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
+
+        let output = String::from_utf8(output.stdout)?;
+        let kube_config = serde_json::from_str::<KubeConfig>(&output)?;
+        Ok(kube_config
+            .contexts
+            .iter()
+            .filter_map(|context| {
+                let cluster = kube_config
+                    .clusters
+                    .iter()
+                    .find(|c| c.name == context.context.cluster)?;
+                Some((context.name.to_owned(), cluster.cluster.server.to_owned()))
+            })
+            .collect())
+    }
+
+    /// Resolves the targeted (or else current) context together with its cluster and user
+    pub fn describe(config: &KubectlConfig) -> Result<ContextInfo> {
+        let output = config
+            .command()
+            .args(["config", "view", "--minify", "--output=json"])
+            .output()?;
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
+
+        let output = String::from_utf8(output.stdout)?;
+        let kube_config = serde_json::from_str::<KubeConfig>(&output)?;
+        let name = config
+            .context
+            .as_ref()
+            .unwrap_or(&kube_config.current_context);
+        let entry = kube_config
+            .contexts
+            .iter()
+            .find(|c| &c.name == name)
+            .ok_or(KubectlError::CommandFailed)?;
+        let server = kube_config
+            .clusters
+            .iter()
+            .find(|c| c.name == entry.context.cluster)
+            .map(|c| c.cluster.server.to_owned());
+
+        Ok(ContextInfo {
+            name: name.to_owned(),
+            cluster: entry.context.cluster.to_owned(),
+            server,
+            user: entry.context.user.to_owned(),
+        })
+    }
+
+    /// Compares context names, ignoring surrounding whitespace
+    pub fn matches(a: &str, b: &str) -> bool {
+        a.trim() == b.trim()
+    }
+
+    /// Makes the namespace the default of the context (or the current one) for later kubectl calls
+    pub fn set_namespace(context: Option<&str>, namespace: &str) -> Result<()> {
+        validate_name(namespace)?;
+        let mut command = kubectl();
+        command.args(["config", "set-context", &format!("--namespace={namespace}")]);
+        match context {
+            Some(context) => command.args(["--", context]),
+            None => command.arg("--current"),
+        };
+        let output = command.output()?;
         if !output.status.success() {
             Err(KubectlError::CommandFailed)
         } else {
             Ok(())
         }
     }
+
+    pub fn set(context: &str) -> Result<()> {
+        let output = kubectl()
+            .args(["config", "use-context", context])
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(
+                match stderr.lines().map(str::trim).rfind(|l| !l.is_empty()) {
+                    Some(line) => KubectlError::Failed(line.to_owned()),
+                    None => KubectlError::CommandFailed,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn trims_crlf_and_trailing_spaces() {
+            let contexts = parse_names("dev\r\nprod/eu-west \r\nkind-kind\t\r\n\r\n");
+            assert_eq!(contexts, ["dev", "prod/eu-west", "kind-kind"]);
+        }
+
+        #[test]
+        fn non_ascii_context_names() {
+            let contexts = parse_names("prød-zürich\r\nPRØD-ZÜRICH\n東京\n");
+            assert_eq!(contexts, ["prød-zürich", "PRØD-ZÜRICH", "東京"]);
+            assert_eq!(duplicates(&contexts), ["prød-zürich", "PRØD-ZÜRICH"]);
+            assert!(matches(&contexts[2], " 東京\n"));
+        }
+
+        #[test]
+        fn default_index_ignores_whitespace() {
+            let contexts = parse_names("dev\r\nprod/eu-west \r\n");
+            let current = "prod/eu-west\r\n";
+            assert_eq!(contexts.iter().position(|c| matches(c, current)), Some(1));
+            assert!(!matches("Prod/eu-west", current));
+        }
+    }
+}
+
+pub mod version {
+    use super::*;
+    use crate::model::KubectlVersion;
+
+    /// Version of the kubectl client (e.g. `v1.30.2`)
+    pub fn client() -> Result<String> {
+        let output = kubectl()
+            .args(["version", "--client", "--output=json"])
+            .output()?;
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
+
+        let output = String::from_utf8(output.stdout)?;
+        Ok(serde_json::from_str::<KubectlVersion>(&output)?
+            .client_version
+            .git_version)
+    }
 }
 
 pub mod namespace {
-    use std::process::Command;
+    use super::*;
+    use crate::model::Namespace;
+
+    pub fn get(
+        config: &KubectlConfig,
+        selector: Option<&str>,
+        progress: Progress,
+    ) -> Result<Vec<Namespace>> {
+        let mut command = config.request_command();
+        command.args(["get", "namespaces", "--output=json"]);
+        if let Some(selector) = selector {
+            command.args(["--selector", selector]);
+        }
+        let output = output(&mut command, progress)?;
+
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
+
+        let output = String::from_utf8(output.stdout)?;
 
+        Ok(dedup(parse_list_or_single::<Namespace>(&output)?))
+    }
+}
+
+pub mod service {
     use super::*;
-    use crate::model::{KubectlList, Namespace};
+    use crate::model::{Endpoints, Service};
+
+    pub fn get(
+        config: &KubectlConfig,
+        namespace: &str,
+        progress: Progress,
+    ) -> Result<Vec<Service>> {
+        let output = output(
+            config.request_command().args([
+                "--namespace",
+                namespace,
+                "get",
+                "services",
+                "--output=json",
+            ]),
+            progress,
+        )?;
+
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
 
-    const KUBECTL: &str = "kubectl";
+        let output = String::from_utf8(output.stdout)?;
+
+        Ok(dedup(parse_services(&output)?))
+    }
 
-    pub fn get() -> Result<Vec<Namespace>> {
-        let output = Command::new(KUBECTL)
-            .args(["get", "namespaces", "--output=json"])
+    /// Looks up the endpoints backing the service
+    pub fn endpoints(config: &KubectlConfig, namespace: &str, service: &str) -> Result<Endpoints> {
+        let output = config
+            .request_command()
+            .args([
+                "--namespace",
+                namespace,
+                "get",
+                "endpoints",
+                service,
+                "--output=json",
+            ])
             .output()?;
 
         if !output.status.success() {
@@ -71,51 +439,413 @@ pub mod namespace {
 
         let output = String::from_utf8(output.stdout)?;
 
-        Ok(serde_json::from_str::<KubectlList<Namespace>>(&output)?.items)
+        Ok(serde_json::from_str::<Endpoints>(&output)?)
+    }
+
+    /// Lists the services of the namespaces, reading them in parallel
+    pub fn get_many(config: &KubectlConfig, namespaces: &[&str]) -> Result<Vec<Service>> {
+        thread::scope(|scope| {
+            let handles = namespaces
+                .iter()
+                .map(|namespace| scope.spawn(|| get(config, namespace, None)))
+                .collect::<Vec<_>>();
+            let mut services = Vec::new();
+            for handle in handles {
+                services.extend(handle.join().map_err(|_| KubectlError::CommandFailed)??);
+            }
+            Ok(dedup(services))
+        })
+    }
+
+    /// Lists the services of all namespaces
+    pub fn get_all(config: &KubectlConfig, progress: Progress) -> Result<Vec<Service>> {
+        let output = output(
+            config
+                .request_command()
+                .args(["get", "services", "--all-namespaces", "--output=json"]),
+            progress,
+        )?;
+
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
+
+        let output = String::from_utf8(output.stdout)?;
+
+        Ok(dedup(parse_services(&output)?))
     }
 }
 
-pub mod service {
-    use std::process::Command;
+/// Time the cluster gets to answer the reachability check
+const CLUSTER_INFO_TIMEOUT: &str = "--request-timeout=3s";
 
+pub mod job {
     use super::*;
-    use crate::model::{KubectlList, Service};
+    use crate::model::{Job, Pod};
 
-    pub fn get(namespace: &str) -> Result<Vec<Service>> {
-        let output = Command::new(KUBECTL)
-            .args(["--namespace", namespace, "get", "services", "--output=json"])
-            .output()?;
+    /// Label the job controller sets on the pods of a job
+    pub const POD_LABEL: &str = "job-name";
 
+    fn get<T: DeserializeOwned>(
+        config: &KubectlConfig,
+        namespace: &str,
+        args: &[&str],
+    ) -> Result<Vec<T>> {
+        validate_name(namespace)?;
+        let output = config
+            .request_command()
+            .args(["--namespace", namespace, "get"])
+            .args(args)
+            .arg("--output=json")
+            .output()?;
         if !output.status.success() {
             return Err(KubectlError::CommandFailed);
         }
 
         let output = String::from_utf8(output.stdout)?;
+        Ok(serde_json::from_str::<KubectlList<T>>(&output)?.items)
+    }
 
-        Ok(serde_json::from_str::<KubectlList<Service>>(&output)?.items)
+    /// Pods of the job, running or already finished
+    pub fn pods(config: &KubectlConfig, namespace: &str, job: &str) -> Result<Vec<Pod>> {
+        validate_name(job)?;
+        get(
+            config,
+            namespace,
+            &["pods", "--selector", &format!("{POD_LABEL}={job}")],
+        )
     }
-}
 
-#[allow(unused)]
-pub fn forward_ports(
-    namespace: &Namespace,
-    service: &Service,
-    ports: &HashMap<u16, u16>,
-) -> Result<Child> {
-    Ok(Command::new(KUBECTL)
-        .args(
-            [
-                "--namespace".into(),
-                namespace.to_string(),
-                "port-forward".into(),
-                format!("service/{}", service),
-            ]
+    /// Names of the jobs the cronjob created, newest first
+    pub fn of_cronjob(
+        config: &KubectlConfig,
+        namespace: &str,
+        cronjob: &str,
+    ) -> Result<Vec<String>> {
+        validate_name(cronjob)?;
+        let mut jobs = get::<Job>(config, namespace, &["jobs"])?
             .into_iter()
-            .chain(
-                ports
+            .filter(|j| {
+                j.metadata
+                    .owner_references
                     .iter()
-                    .map(|(remote_port, local_port)| format!("{local_port}:{remote_port}")),
-            ),
-        )
+                    .any(|o| o.kind.as_deref() == Some("CronJob") && o.name == cronjob)
+            })
+            .collect::<Vec<_>>();
+        jobs.sort_by(|a, b| {
+            b.metadata
+                .creation_timestamp
+                .cmp(&a.metadata.creation_timestamp)
+        });
+        Ok(jobs.into_iter().map(|j| j.metadata.name).collect())
+    }
+}
+
+pub mod pod {
+    use super::*;
+    use crate::model::Pod;
+
+    pub fn get(config: &KubectlConfig, namespace: &str, pod: &str) -> Result<Pod> {
+        validate_name(namespace)?;
+        let output = config
+            .request_command()
+            .args([
+                "--namespace",
+                namespace,
+                "get",
+                "--output=json",
+                "--",
+                &format!("pod/{pod}"),
+            ])
+            .output()?;
+        if !output.status.success() {
+            return Err(KubectlError::CommandFailed);
+        }
+
+        let output = String::from_utf8(output.stdout)?;
+        Ok(serde_json::from_str::<Pod>(&output)?)
+    }
+}
+
+/// Parts of kubectl's error when it couldn't reach the API server at all
+const CONNECTION_ERRORS: [&str; 6] = [
+    "Unable to connect to the server",
+    "connection refused",
+    "i/o timeout",
+    "no such host",
+    "context deadline exceeded",
+    "Client.Timeout exceeded",
+];
+
+/// Checks that the API server of the context answers, failing with kubectl's error otherwise.
+/// Any answer counts, e.g. a Forbidden under namespace-scoped RBAC still means it's reachable.
+pub fn check_cluster(config: &KubectlConfig) -> Result<()> {
+    let output = config
+        .command()
+        .args([CLUSTER_INFO_TIMEOUT, "get", "--raw=/readyz"])
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    match connection_error(&String::from_utf8_lossy(&output.stderr)) {
+        Some(detail) => Err(KubectlError::Unreachable(detail.to_owned())),
+        None => Ok(()),
+    }
+}
+
+/// The last line of kubectl's stderr telling it couldn't connect, if any
+fn connection_error(stderr: &str) -> Option<&str> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .rfind(|line| CONNECTION_ERRORS.iter().any(|e| line.contains(e)))
+}
+
+/// Checks whether the current user may list the resource in the namespace
+/// (or in all namespaces)
+pub fn can_list(config: &KubectlConfig, resource: &str, namespace: Option<&str>) -> Result<bool> {
+    let mut command = config.request_command();
+    command.args(["auth", "can-i", "list", resource]);
+    match namespace {
+        Some(namespace) => command.args(["--namespace", namespace]),
+        None => command.arg("--all-namespaces"),
+    };
+    // NOTE: The exit code is nonzero for "no" as well, so only the answer counts
+    let output = command.output()?;
+    match String::from_utf8(output.stdout)?.trim() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(KubectlError::CommandFailed),
+    }
+}
+
+/// Ensures a user supplied name is a valid Kubernetes (DNS-1123 label) name,
+/// so it can't be misinterpreted as a flag by kubectl
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(KubectlError::InvalidName(name.to_owned()))
+    }
+}
+
+/// Everything describing a single `kubectl port-forward`
+#[derive(Debug, Clone)]
+pub struct ForwardSpec {
+    pub namespace: Namespace,
+    pub service: Service,
+    /// Local port for each remote port
+    pub ports: HashMap<u16, u16>,
+    /// Local address to listen on (kubectl defaults to localhost)
+    pub address: Option<IpAddr>,
+    /// Pod to forward to directly on the target ports, bypassing the service
+    pub pod: Option<String>,
+}
+impl ForwardSpec {
+    /// Resource kubectl forwards to (e.g. `service/api`)
+    pub fn target(&self) -> String {
+        match &self.pod {
+            Some(pod) => format!("pod/{pod}"),
+            None => format!("service/{}", self.service),
+        }
+    }
+
+    /// Port of the target the given service port is forwarded to, by name for named
+    /// target ports of a pod (kubectl resolves those against the pod's container ports)
+    pub fn remote_port(&self, port: u16) -> TargetPort {
+        if self.pod.is_none() {
+            return TargetPort::Number(port);
+        }
+        self.service
+            .spec
+            .ports
+            .iter()
+            .find(|p| p.port == port)
+            .and_then(|p| p.target_port.clone())
+            .unwrap_or(TargetPort::Number(port))
+    }
+}
+
+/// Arguments for `kubectl port-forward`, mapping each remote port to its local port
+pub fn build_forward_args(spec: &ForwardSpec, extra_args: &[String]) -> Result<Vec<String>> {
+    // Prefer the namespace the service reports itself, it's the one it actually lives in
+    let namespace = spec
+        .service
+        .namespace()
+        .unwrap_or_else(|| spec.namespace.to_owned());
+    validate_name(&namespace.metadata.name)?;
+    validate_name(&spec.service.metadata.name)?;
+
+    let mut args = vec![
+        "--namespace".into(),
+        namespace.to_string(),
+        "port-forward".into(),
+    ];
+    if let Some(address) = spec.address {
+        args.extend(["--address".into(), address.to_string()]);
+    }
+    args.extend(extra_args.iter().cloned());
+    // NOTE: Everything after `--` is positional, never a flag
+    args.extend(["--".into(), spec.target()]);
+    args.extend(spec.ports.iter().map(|(remote_port, local_port)| {
+        format!("{local_port}:{}", spec.remote_port(*remote_port))
+    }));
+    Ok(args)
+}
+
+/// Follows the logs of all pods matching the selector, each line prefixed by its pod
+pub fn tail_logs(config: &KubectlConfig, namespace: &Namespace, selector: &str) -> Result<Child> {
+    validate_name(&namespace.metadata.name)?;
+    Ok(config
+        .command()
+        .args([
+            "--namespace",
+            &namespace.metadata.name,
+            "logs",
+            "--follow",
+            "--prefix",
+            "--all-containers",
+            "--tail=10",
+            "--max-log-requests=10",
+            "--selector",
+            selector,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?)
+}
+
+pub fn forward_ports(config: &KubectlConfig, spec: &ForwardSpec) -> Result<Child> {
+    Ok(config
+        .command()
+        .args(build_forward_args(spec, &config.forward_args)?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup_keeps_the_first_of_each() {
+        let namespaces = ["web", "db", "web", "kube-system", "db"].map(Namespace::named);
+        let names = dedup(namespaces.to_vec())
+            .iter()
+            .map(|ns| ns.metadata.name.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["web", "db", "kube-system"]);
+    }
+
+    #[test]
+    fn parses_list_shape() {
+        let output = r#"{"apiVersion": "v1", "kind": "List", "items": [
+            {"metadata": {"name": "web"}}, {"metadata": {"name": "db"}}
+        ]}"#;
+        let namespaces = parse_list_or_single::<Namespace>(output).unwrap();
+        assert_eq!(namespaces, ["web", "db"].map(Namespace::named));
+    }
+
+    #[test]
+    fn parses_single_object_shape() {
+        let output = r#"{"apiVersion": "v1", "kind": "Namespace", "metadata": {"name": "web"}}"#;
+        let namespaces = parse_list_or_single::<Namespace>(output).unwrap();
+        assert_eq!(namespaces, [Namespace::named("web")]);
+    }
+
+    #[test]
+    fn rejects_other_json() {
+        assert!(parse_list_or_single::<Namespace>(r#"{"kind": "Status"}"#).is_err());
+        assert!(parse_list_or_single::<Namespace>("[]").is_err());
+    }
+
+    /// A service as printed by `kubectl get service --output=json`
+    const SERVICE: &str = r#"{
+        "apiVersion": "v1",
+        "kind": "Service",
+        "metadata": {
+            "annotations": {
+                "meta.helm.sh/release-name": "api"
+            },
+            "creationTimestamp": "2026-10-01T08:12:44Z",
+            "labels": {
+                "app.kubernetes.io/name": "api"
+            },
+            "name": "api",
+            "namespace": "web",
+            "resourceVersion": "48213",
+            "uid": "0b6f4b4e-6f1c-4c43-9a43-2f1de3c1f0aa"
+        },
+        "spec": {
+            "clusterIP": "10.96.12.7",
+            "clusterIPs": ["10.96.12.7"],
+            "internalTrafficPolicy": "Cluster",
+            "ipFamilies": ["IPv4"],
+            "ipFamilyPolicy": "SingleStack",
+            "ports": [
+                {"name": "http", "port": 80, "protocol": "TCP", "targetPort": "http"},
+                {"name": "metrics", "port": 9090, "protocol": "TCP", "targetPort": 9090}
+            ],
+            "selector": {
+                "app.kubernetes.io/name": "api"
+            },
+            "sessionAffinity": "None",
+            "type": "ClusterIP"
+        },
+        "status": {
+            "loadBalancer": {}
+        }
+    }"#;
+
+    #[test]
+    fn parses_service_list() {
+        let output = format!(
+            r#"{{"apiVersion": "v1", "items": [{SERVICE}], "kind": "List", "metadata": {{"resourceVersion": ""}}}}"#
+        );
+        let services = parse_services(&output).unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].key(), "web/api");
+        assert_eq!(services[0].spec.ports.len(), 2);
+        assert_eq!(services[0].raw["spec"]["sessionAffinity"], "None");
+    }
+
+    #[test]
+    fn parses_single_service() {
+        let services = parse_services(SERVICE).unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].key(), "web/api");
+        assert_eq!(services[0].spec.service_type(), "ClusterIP");
+    }
+
+    #[test]
+    fn parses_null_items_as_empty() {
+        let output = r#"{"apiVersion": "v1", "items": null, "kind": "List"}"#;
+        assert!(parse_services(output).unwrap().is_empty());
+    }
+
+    #[test]
+    fn connection_error_is_unreachable() {
+        let stderr = "E1016 memcache.go:265] couldn't get current server API group list: \
+                      dial tcp 10.0.0.1:6443: i/o timeout\n\
+                      Unable to connect to the server: dial tcp 10.0.0.1:6443: i/o timeout\n";
+        assert_eq!(
+            connection_error(stderr),
+            Some("Unable to connect to the server: dial tcp 10.0.0.1:6443: i/o timeout")
+        );
+    }
+
+    #[test]
+    fn forbidden_is_reachable() {
+        let stderr =
+            "Error from server (Forbidden): forbidden: User \"dev\" cannot get path \"/readyz\"\n";
+        assert_eq!(connection_error(stderr), None);
+    }
+}
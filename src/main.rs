@@ -1,71 +1,309 @@
+mod cli;
+mod dotenv;
 mod error;
+mod forward;
 mod kubectl;
 mod model;
+mod picker;
+mod registry;
 mod selection;
+mod session;
+mod summary;
+mod toml;
+mod validate;
+mod yaml;
 
-use std::collections::HashMap;
-use std::process::ExitCode;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitCode};
+use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use std::{fs, thread};
 
-use dialoguer::MultiSelect;
-use dialoguer::{FuzzySelect, Input, theme::Theme};
-use indicatif::ProgressBar;
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme};
+use dialoguer::{Confirm, MultiSelect, Select};
+use dialoguer::{FuzzySelect, Input};
+use indicatif::{ProgressBar, ProgressStyle};
 
-use crate::error::MainError;
-use crate::kubectl::{context, namespace, service};
-use crate::model::{Namespace, Service};
+use crate::cli::{Args, ConfigFormat, ContextSort, LocalPort, OutputFormat, PassTo, Subcommand};
+use crate::error::{KubectlError, MainError};
+use crate::forward::{ForwardHandle, HealthCheck, Keepalive, LogTail};
+use crate::kubectl::{ForwardSpec, KubectlConfig, context, namespace, service};
+use crate::model::{Namespace, Port, Service, TargetPort};
 use crate::selection::{DefaultSelections, Selection};
+use crate::session::SessionRecord;
 
 type Result<T> = std::result::Result<T, MainError>;
 
-fn preselect_context(theme: &dyn Theme) -> Result<()> {
-    let contexts = context::get()?;
+/// How often `--wait` looks for a ready endpoint
+const ENDPOINTS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often `--live-picker` reloads the services while the selection is shown
+const LIVE_PICKER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Services which are rarely meant to be forwarded, but easily picked by mistake
+const CONFIRMED_SERVICES: [&str; 2] = ["default/kubernetes", "kube-system/kube-dns"];
+
+/// Output format for errors, set once the arguments are parsed
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Preferences applied to every selection prompt
+#[derive(Debug, Clone, Copy)]
+struct PromptOptions<'a> {
+    fuzzy: bool,
+    /// Take the default or first (sorted) item instead of prompting
+    select_first: bool,
+    /// Dotted path of the value shown next to each service
+    display_column: Option<&'a str>,
+    verbose: bool,
+    /// Include the number of items in the prompt
+    show_counts: bool,
+    /// Fail instead of prompting whenever the choice isn't determined already
+    strict: bool,
+    /// Keep reloading the services while the service selection is shown
+    live_picker: bool,
+    /// Skip the port selection for services with a single port
+    auto_select_single_port: bool,
+    context_sort: ContextSort,
+    /// Contexts forwarded in, most recent first
+    recent_contexts: &'a [String],
+}
+impl PromptOptions<'_> {
+    /// Whether questions beyond the selections (e.g. about running forwards) may be asked
+    fn may_prompt(&self) -> bool {
+        !self.strict && !self.select_first
+    }
+}
+
+/// Theme of all prompts, plain when colors are disabled (`--no-color`, `NO_COLOR` or no terminal)
+fn build_theme(no_color: bool) -> Box<dyn Theme + Sync> {
+    if no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    if !console::colors_enabled_stderr() {
+        Box::new(SimpleTheme)
+    } else {
+        Box::new(ColorfulTheme::default())
+    }
+}
+
+/// Lets the user pick one of the items, using a fuzzy or a plain selection
+fn select_item<T: ToString>(
+    theme: &dyn Theme,
+    options: PromptOptions,
+    prompt: &str,
+    items: &[T],
+    default: Option<usize>,
+) -> Result<usize> {
+    if options.select_first {
+        let first = (0..items.len()).min_by_key(|i| items[*i].to_string());
+        return Ok(default.or(first).unwrap_or(0));
+    }
+    if options.strict {
+        return default.or((items.len() == 1).then_some(0)).ok_or_else(|| {
+            MainError::PromptRequired(prompt.to_owned(), items.iter().map(T::to_string).collect())
+        });
+    }
+    let prompt = match (options.show_counts, options.fuzzy) {
+        (false, _) => Cow::Borrowed(prompt),
+        (true, false) => Cow::Owned(format!("{prompt} ({})", items.len())),
+        (true, true) => Cow::Owned(format!("{prompt} ({}, type to filter)", items.len())),
+    };
+    if options.fuzzy {
+        let mut select = FuzzySelect::with_theme(theme)
+            .with_prompt(prompt)
+            .items(items);
+        if let Some(i) = default {
+            select = select.default(i);
+        }
+        Ok(select.interact()?)
+    } else {
+        let mut select = Select::with_theme(theme).with_prompt(prompt).items(items);
+        if let Some(i) = default {
+            select = select.default(i);
+        }
+        Ok(select.interact()?)
+    }
+}
+
+fn preselect_context(
+    theme: &dyn Theme,
+    options: PromptOptions,
+    requested: Option<&str>,
+    glob: Option<&str>,
+    interactive: bool,
+    skip: bool,
+) -> Result<()> {
+    let mut contexts = context::get()?;
     if contexts.is_empty() {
         return Err(MainError::NoContext);
     }
+
+    // Switch to the explicitly requested context without prompting
+    if let Some(requested) = requested {
+        let ctx = contexts
+            .iter()
+            .find(|ctx| context::matches(ctx, requested))
+            .ok_or_else(|| MainError::ContextNotFound(requested.to_owned()))?;
+        switch_context(ctx)?;
+        return Ok(());
+    }
+
+    // Narrow down the contexts by the pattern, switching right away to a single match
+    if let Some(glob) = glob {
+        contexts.retain(|ctx| cli::glob_match(glob, ctx));
+        match contexts.len() {
+            0 => return Err(MainError::NoContextMatching(glob.to_owned())),
+            1 => {
+                switch_context(&contexts[0])?;
+                return Ok(());
+            }
+            _ if !interactive => {
+                return Err(MainError::AmbiguousContext(glob.to_owned(), contexts));
+            }
+            _ => {}
+        }
+    }
+
+    // Stick to the current context
+    if skip {
+        return match context::current() {
+            Ok(ctx) if !ctx.is_empty() => Ok(()),
+            _ => Err(MainError::NoContext),
+        };
+    }
     let current_ctx = context::current().unwrap_or(String::from(""));
 
     if contexts.len() > 1 {
-        let mut prompt = dialoguer::FuzzySelect::with_theme(theme)
-            .with_prompt("Select context")
-            .items(&contexts);
-        let default_idx = contexts.iter().position(|ctx| current_ctx.eq(ctx));
-        if let Some(i) = default_idx {
-            prompt = prompt.default(i);
+        contexts.sort();
+        match options.context_sort {
+            ContextSort::Alphabetical => {}
+            ContextSort::Recent => contexts.sort_by_key(|ctx| {
+                options
+                    .recent_contexts
+                    .iter()
+                    .position(|r| context::matches(r, ctx))
+                    .unwrap_or(usize::MAX)
+            }),
+            ContextSort::CurrentFirst => {
+                contexts.sort_by_key(|ctx| !context::matches(ctx, &current_ctx))
+            }
+        }
+        let default_idx = contexts
+            .iter()
+            .position(|ctx| context::matches(ctx, &current_ctx));
+        // Tell similarly named contexts apart by their cluster
+        let duplicates = context::duplicates(&contexts);
+        let servers = if duplicates.is_empty() {
+            HashMap::new()
+        } else {
+            if options.verbose {
+                eprintln!(
+                    "Warning: found similarly named contexts: {}",
+                    duplicates.join(", ")
+                );
+            }
+            context::servers().unwrap_or_default()
+        };
+        let items = contexts
+            .iter()
+            .map(|ctx| match servers.get(ctx) {
+                Some(server) if duplicates.contains(&ctx.as_str()) => format!("{ctx} ({server})"),
+                _ => ctx.to_owned(),
+            })
+            .collect::<Vec<_>>();
+        let mut selected_idx = select_item(theme, options, "Select context", &items, default_idx)?;
+        // Offer to retry (e.g. a locked kubeconfig) or to pick another context on failure
+        while let Err(e) = switch_context(&contexts[selected_idx]) {
+            if options.select_first || options.strict {
+                return Err(e);
+            }
+            eprintln!("{e}");
+            let choice = Select::with_theme(theme)
+                .with_prompt("What now?")
+                .items(&["Retry", "Pick a different context", "Abort"])
+                .default(0)
+                .interact()?;
+            match choice {
+                0 => {}
+                1 => {
+                    selected_idx =
+                        select_item(theme, options, "Select context", &items, Some(selected_idx))?
+                }
+                _ => return Err(e),
+            }
         }
-        let selected_idx = prompt.interact()?;
-
-        context::set(&contexts[selected_idx]).expect("Failed to select context");
     }
     Ok(())
 }
 
-fn select_namespace(theme: &dyn Theme, default: Option<String>) -> Result<Namespace> {
+/// Makes the context kubectl's current one
+fn switch_context(ctx: &str) -> Result<()> {
+    context::set(ctx).map_err(|e| MainError::ContextSwitchFailed(ctx.to_owned(), e))
+}
+
+/// Context the last service was forwarded in, for `--interactive-ports-only` without an
+/// explicitly given context
+fn remembered_context(args: &Args, recent_contexts: &[String]) -> Option<String> {
+    if !args.interactive_ports_only || args.context.is_some() || args.context_glob.is_some() {
+        return None;
+    }
+    recent_contexts.first().cloned()
+}
+
+/// Spinner for a kubectl call, showing how long it has been waiting already
+fn spinner(message: impl Into<Cow<'static, str>>) -> ProgressBar {
+    let spinner = ProgressBar::new_spinner().with_message(message);
+    if let Ok(style) = ProgressStyle::with_template("{spinner} {msg} {elapsed:.dim}") {
+        spinner.set_style(style);
+    }
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+fn select_namespace(
+    theme: &dyn Theme,
+    options: PromptOptions,
+    kubectl_config: &KubectlConfig,
+    selector: Option<&str>,
+    default: Option<String>,
+) -> Result<Namespace> {
     // Loading namespaces
-    let bar = ProgressBar::new_spinner().with_message("Getting available namespaces...");
-    bar.enable_steady_tick(Duration::from_millis(100));
-    let namespaces = namespace::get()?;
+    let bar = spinner("Getting available namespaces...");
+    let progress =
+        |message: &str| bar.set_message(format!("Getting available namespaces, {message}"));
+    let namespaces = namespace::get(kubectl_config, selector, Some(&progress))?;
     bar.finish_and_clear();
 
     // Ensure at least one is available
     if namespaces.is_empty() {
-        return Err(MainError::NoNamespace);
+        // An empty list may as well mean the user isn't allowed to see any
+        if !kubectl::can_list(kubectl_config, "namespaces", None).unwrap_or(true) {
+            return Err(MainError::PermissionDenied(
+                String::from("namespaces"),
+                None,
+            ));
+        }
+        return Err(match selector {
+            Some(selector) => MainError::NoNamespaceMatching(selector.to_owned()),
+            None => MainError::NoNamespace,
+        });
     }
 
     // Show selection if more than one namespace
     if namespaces.len() > 1 {
-        let mut prompt = FuzzySelect::with_theme(theme)
-            .with_prompt("Select namespace")
-            .items(&namespaces);
         let default_idx =
             default.and_then(|d| namespaces.iter().position(|ns| ns.metadata.name.eq(&d)));
-        if let Some(i) = default_idx {
-            prompt = prompt.default(i);
-        }
-        let selected_idx = prompt.interact()?;
+        let selected_idx =
+            select_item(theme, options, "Select namespace", &namespaces, default_idx)?;
         Ok(namespaces[selected_idx].to_owned())
     } else {
         // NOTE: Checked previously that at least one exists
@@ -74,33 +312,174 @@ fn select_namespace(theme: &dyn Theme, default: Option<String>) -> Result<Namesp
 }
 
 fn select_service(
-    theme: &dyn Theme,
-    namespace: &Namespace,
-    default: Option<String>,
+    theme: &(dyn Theme + Sync),
+    options: PromptOptions,
+    kubectl_config: &KubectlConfig,
+    namespaces: &[Namespace],
+    service_type: Option<&str>,
+    requested: Option<&str>,
+    defaults: Option<&DefaultSelections>,
 ) -> Result<Service> {
-    // Loading services of given namespace
-    let spinner = ProgressBar::new_spinner().with_message(format!(
-        "Reading services of {}...",
-        namespace.metadata.name
-    ));
-    spinner.enable_steady_tick(Duration::from_millis(100));
-    let services = service::get(&namespace.metadata.name)?;
+    // Loading services of the given namespaces (or all of them if none are given)
+    let names = namespaces
+        .iter()
+        .map(|n| n.metadata.name.as_str())
+        .collect::<Vec<_>>();
+    let spinner = spinner(match names.as_slice() {
+        [] => String::from("Reading services of all namespaces..."),
+        names => format!("Reading services of {}...", names.join(", ")),
+    });
+    let reading = spinner.message();
+    let progress = |message: &str| {
+        spinner.set_message(format!("{}, {message}", reading.trim_end_matches("...")))
+    };
+    let list = |progress: kubectl::Progress| match names.as_slice() {
+        [] => service::get_all(kubectl_config, progress),
+        [namespace] => service::get(kubectl_config, namespace, progress),
+        names => service::get_many(kubectl_config, names),
+    };
+    let services = list(Some(&progress))?;
     spinner.finish_and_clear();
+    if names.len() != 1 && !services.is_empty() {
+        let namespaces = services
+            .iter()
+            .filter_map(|s| s.metadata.namespace.as_deref())
+            .collect::<HashSet<_>>();
+        eprintln!(
+            "Found {} services in {} namespaces ({})",
+            services.len(),
+            namespaces.len(),
+            cli::format_duration(spinner.elapsed())
+        );
+    }
 
+    let namespace_name = match names.as_slice() {
+        [] => String::from("*"),
+        names => names.join(","),
+    };
     if services.is_empty() {
-        return Err(MainError::NoService(namespace.metadata.name.to_owned()));
+        // An empty list may as well mean the user isn't allowed to see any
+        let checked = match names.as_slice() {
+            [] => vec![None],
+            names => names.iter().copied().map(Some).collect(),
+        };
+        for namespace in checked {
+            if !kubectl::can_list(kubectl_config, "services", namespace).unwrap_or(true) {
+                return Err(MainError::PermissionDenied(
+                    String::from("services"),
+                    namespace.map(String::from),
+                ));
+            }
+        }
+        return Err(MainError::NoService(namespace_name));
+    }
+    let of_type = |services: Vec<Service>| {
+        services
+            .into_iter()
+            .filter(|s| service_type.is_none_or(|t| s.spec.service_type().eq_ignore_ascii_case(t)))
+            .collect::<Vec<_>>()
+    };
+    let services = match service_type {
+        Some(service_type) => {
+            let services = of_type(services);
+            if services.is_empty() {
+                return Err(MainError::NoServiceOfType(
+                    service_type.to_owned(),
+                    namespace_name,
+                ));
+            }
+            services
+        }
+        None => services,
+    };
+
+    // Use the explicitly requested service without prompting
+    if let Some(requested) = requested {
+        for part in requested.split('/') {
+            kubectl::validate_name(part)?;
+        }
+        let mut matching = services
+            .into_iter()
+            .filter(|s| s.metadata.name == requested || s.key() == requested)
+            .collect::<Vec<_>>();
+        return match matching.len() {
+            0 => Err(MainError::ServiceNotFound(
+                requested.to_owned(),
+                namespace_name,
+            )),
+            1 => Ok(matching.remove(0)),
+            _ => Err(MainError::AmbiguousService(
+                requested.to_owned(),
+                matching.iter().map(Service::key).collect(),
+            )),
+        };
     }
 
     if services.len() > 1 {
-        let mut prompt = FuzzySelect::with_theme(theme)
-            .with_prompt("Select service")
-            .items(&services);
-        let default_idx =
-            default.and_then(|d| services.iter().position(|s| s.metadata.name.eq(&d)));
-        if let Some(i) = default_idx {
-            prompt = prompt.default(i);
+        // Qualify the names, as services of different namespaces may share them
+        let remembered = defaults.and_then(|d| d.ports.as_ref());
+        let label = |s: &Service, warn: bool| {
+            let mut name = service_label(s, names.len() != 1);
+            if let Some(path) = options.display_column {
+                match s.column(path) {
+                    Some(value) => name = format!("{name} [{value}]"),
+                    None if warn && options.verbose => {
+                        eprintln!("Warning: '{path}' has no value for service {}", s.key())
+                    }
+                    None => {}
+                }
+            }
+            // Hint at the remembered mappings, e.g. `api (80→18080)`
+            let ports = remembered
+                .and_then(|r| r.get(&s.key()).or_else(|| r.get(&s.metadata.name)))
+                .filter(|p| !p.is_empty());
+            match ports {
+                Some(ports) => {
+                    let mut mappings = ports.iter().collect::<Vec<_>>();
+                    mappings.sort();
+                    let mappings = mappings
+                        .iter()
+                        .map(|(remote, local)| format!("{remote}→{local}"))
+                        .collect::<Vec<_>>();
+                    format!("{name} ({})", mappings.join(", "))
+                }
+                None => name,
+            }
+        };
+        let default_idx = defaults
+            .and_then(|d| d.last_service.as_ref())
+            .and_then(|d| {
+                services
+                    .iter()
+                    .position(|s| s.key().eq(d) || s.metadata.name.eq(d))
+            });
+        if options.live_picker
+            && !options.select_first
+            && !options.strict
+            && io::stderr().is_terminal()
+        {
+            let labeled = |services: Vec<Service>| {
+                services
+                    .into_iter()
+                    .map(|s| (label(&s, false), s))
+                    .collect::<Vec<_>>()
+            };
+            let prompt = format!(
+                "Select service (reloaded every {})",
+                cli::format_duration(LIVE_PICKER_INTERVAL)
+            );
+            return Ok(picker::pick(
+                theme,
+                &prompt,
+                labeled(services),
+                default_idx,
+                LIVE_PICKER_INTERVAL,
+                || list(None).ok().map(of_type).map(labeled),
+            )
+            .map_err(dialoguer::Error::IO)?);
         }
-        let selected_idx = prompt.interact()?;
+        let items = services.iter().map(|s| label(s, true)).collect::<Vec<_>>();
+        let selected_idx = select_item(theme, options, "Select service", &items, default_idx)?;
         Ok(services[selected_idx].to_owned())
     } else {
         // NOTE: Checked previously that at least one exists
@@ -108,154 +487,1676 @@ fn select_service(
     }
 }
 
+/// Namespace given by `<namespace>/<service>` or `--namespace` (from the flags, else from the
+/// `KPFR_*` environment), which replaces selecting one with the remembered one preselected
+fn given_namespace(args: &Args) -> Option<Namespace> {
+    args.service
+        .as_deref()
+        .and_then(|s| s.split_once('/'))
+        .map(|(ns, _)| Namespace::named(ns))
+        .or_else(|| args.namespace.as_deref().map(Namespace::named))
+}
+
+/// Name of the service in the selection, qualified (`namespace/service`) when listing the
+/// services of several namespaces, so the filter matches the namespace as well
+fn service_label(service: &Service, qualified: bool) -> String {
+    if qualified {
+        service.key()
+    } else {
+        service.metadata.name.to_owned()
+    }
+}
+
 fn select_remote_ports(
     theme: &dyn Theme,
+    options: PromptOptions,
     service: &Service,
-    default_ports: &HashMap<u16, u16>,
+    remembered: Option<&HashMap<u16, u16>>,
 ) -> Result<Vec<u16>> {
-    let default_ports = default_ports
-        .keys()
-        .map(|k| k.to_owned())
-        .collect::<Vec<_>>();
-    let port_items = service.spec.ports.clone();
+    if service.spec.ports.is_empty() {
+        return Err(MainError::NoServicePorts(service.key()));
+    }
+    // Pre-check the ports remembered for this particular service, grouping them by protocol
+    let mut port_items = service.spec.ports.clone();
+    port_items.sort_by_key(|p| p.protocol() != "TCP");
+    // NOTE: kubectl port-forward only supports TCP, so other ports are never picked implicitly
     let ports = port_items
         .iter()
-        .map(|p| (p.port, default_ports.contains(&p.port)))
+        .map(|p| {
+            let tcp = p.protocol() == "TCP";
+            (
+                p.port,
+                tcp && remembered.is_some_and(|r| r.contains_key(&p.port)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let tcp_ports = port_items
+        .iter()
+        .filter(|p| p.protocol() == "TCP")
+        .map(|p| p.port)
+        .collect::<Vec<_>>();
+    if tcp_ports.is_empty() {
+        return Err(unsupported_protocol(service, &port_items));
+    }
+    let mixed = tcp_ports.len() < port_items.len();
+    let items = port_items
+        .iter()
+        .zip(&ports)
+        .map(|(port, (number, checked))| {
+            let label = match port.protocol() {
+                _ if !mixed => number.to_string(),
+                "TCP" => format!("{number} {}", console::style("TCP").for_stderr().cyan()),
+                protocol => format!(
+                    "{number} {}",
+                    console::style(format!("{protocol}, not forwardable"))
+                        .for_stderr()
+                        .yellow()
+                ),
+            };
+            (label, *checked)
+        })
         .collect::<Vec<_>>();
 
-    if ports.len() == 1 {
+    let prompt = tcp_ports.len() > 1 || !options.auto_select_single_port;
+    if prompt && options.strict && !options.select_first {
+        let checked = ports
+            .iter()
+            .filter(|p| p.1)
+            .map(|p| p.0)
+            .collect::<Vec<_>>();
+        if checked.is_empty() {
+            return Err(MainError::PromptRequired(
+                format!("Select ports of {service} to forward"),
+                tcp_ports.iter().map(u16::to_string).collect(),
+            ));
+        }
+        Ok(checked)
+    } else if prompt && !options.select_first {
         let selections = MultiSelect::with_theme(theme)
-            .items_checked(&ports)
+            .with_prompt(format!("Select ports of {service} to forward"))
+            .items_checked(&items)
             .interact()?;
-        Ok(selections
+        let selected = selections
+            .iter()
+            .map(|s| &port_items[*s])
+            .collect::<Vec<_>>();
+        let unsupported = selected
+            .iter()
+            .filter(|p| p.protocol() != "TCP")
+            .map(|p| (*p).clone())
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            return Err(unsupported_protocol(service, &unsupported));
+        }
+        Ok(selected.iter().map(|p| p.port).collect())
+    } else {
+        Ok(tcp_ports)
+    }
+}
+
+fn unsupported_protocol(service: &Service, ports: &[Port]) -> MainError {
+    MainError::UnsupportedProtocol(
+        service.key(),
+        ports
             .iter()
-            .map(|s| port_items[*s].port)
-            .collect::<Vec<_>>())
+            .filter(|p| p.protocol() != "TCP")
+            .map(|p| format!("{}/{}", p.port, p.protocol()))
+            .collect(),
+    )
+}
+
+/// Requires typing the context name before forwarding against a protected context
+fn confirm_protected_context(
+    theme: &dyn Theme,
+    protected: &[String],
+    context: Option<&str>,
+    strict: bool,
+) -> Result<()> {
+    if protected.is_empty() {
+        return Ok(());
+    }
+    let ctx = match context {
+        Some(context) => context.to_owned(),
+        None => context::current()?,
+    };
+    if !protected.iter().any(|p| cli::glob_match(p, &ctx)) {
+        return Ok(());
+    }
+
+    if strict {
+        return Err(MainError::NotConfirmed(ctx));
+    }
+    eprintln!("Context '{ctx}' is protected.");
+    let typed = Input::<String>::with_theme(theme)
+        .with_prompt("Type the context name to confirm")
+        .allow_empty(true)
+        .interact_text()?;
+    if typed.trim() == ctx {
+        Ok(())
+    } else {
+        Err(MainError::NotConfirmed(ctx))
+    }
+}
+
+/// Asks before forwarding a system service picked in the selection (like the API server's
+/// `kubernetes` service), which is usually a slip of the fuzzy matching
+fn confirm_system_service(
+    theme: &dyn Theme,
+    options: PromptOptions,
+    patterns: Option<&[String]>,
+    service: &Service,
+) -> Result<()> {
+    let key = service.key();
+    let matches = match patterns {
+        Some(patterns) => patterns.iter().any(|p| cli::glob_match(p, &key)),
+        None => CONFIRMED_SERVICES.iter().any(|p| cli::glob_match(p, &key)),
+    };
+    if !matches {
+        return Ok(());
+    }
+
+    if options.strict {
+        return Err(MainError::ServiceNotConfirmed(key));
+    }
+    eprintln!("Note: {key} is a system service, which is rarely what you want to forward");
+    if options.select_first {
+        return Ok(());
+    }
+    let confirmed = Confirm::with_theme(theme)
+        .with_prompt(format!("Forward {key} anyway?"))
+        .default(false)
+        .interact()?;
+    if confirmed {
+        Ok(())
     } else {
-        Ok(ports.iter().map(|p| p.0).collect())
+        Err(MainError::ServiceNotConfirmed(key))
+    }
+}
+
+/// Guards against running kpfr instances holding any of the local ports: stops them
+/// (`--replace` or when chosen), moves to different local ports, or warns when not interactive
+fn replace_running_forwards(
+    theme: &dyn Theme,
+    config_dir: &Path,
+    ports: &mut HashMap<u16, u16>,
+    replace: bool,
+    interactive: bool,
+) -> Result<()> {
+    let mut owners = ports
+        .values()
+        .filter(|port| **port != 0)
+        .filter_map(|port| registry::owner_of(config_dir, *port).map(|owner| (*port, owner)))
+        .collect::<Vec<_>>();
+    owners.sort_by_key(|(port, owner)| (owner.pid, *port));
+    owners.dedup_by_key(|(_, owner)| owner.pid);
+
+    for (port, owner) in owners {
+        let message = format!(
+            "Port {port} is already forwarded to {} by kpfr (pid {})",
+            owner.service, owner.pid
+        );
+        let choice = if replace {
+            0
+        } else if interactive && console::Term::stderr().is_term() {
+            Select::with_theme(theme)
+                .with_prompt(message)
+                .items(&[
+                    "Stop it and take over",
+                    "Use a different local port",
+                    "Keep the port anyway",
+                ])
+                .default(0)
+                .interact()?
+        } else {
+            eprintln!("Warning: {message}, use --replace to stop it");
+            continue;
+        };
+        match choice {
+            0 => {
+                if owner.terminate()? {
+                    eprintln!(
+                        "Stopped kpfr (pid {}) forwarding {}",
+                        owner.pid, owner.service
+                    );
+                } else {
+                    eprintln!("Warning: kpfr (pid {}) did not stop in time", owner.pid);
+                }
+            }
+            1 => {
+                let taken = owner.ports.values().copied().collect::<HashSet<_>>();
+                for local_port in ports.values_mut().filter(|p| taken.contains(p)) {
+                    let free = free_port_from(local_port.saturating_add(1));
+                    eprintln!("Using local port {free} instead of {local_port}");
+                    *local_port = free;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Ensures all given remote ports are exposed by the service
+fn validate_remote_ports<'a>(
+    service: &Service,
+    remote_ports: impl IntoIterator<Item = &'a u16>,
+) -> Result<()> {
+    for port in remote_ports {
+        if !service.spec.ports.iter().any(|p| p.port == *port) {
+            return Err(MainError::UnknownPort(*port, service.key()));
+        }
+    }
+    Ok(())
+}
+
+/// First local port that can be bound without elevated privileges
+fn unprivileged_port_start() -> u16 {
+    if cfg!(windows) {
+        return 0;
     }
+    if !cfg!(target_os = "linux") {
+        return 1024;
+    }
+    // NOTE: CAP_NET_BIND_SERVICE (bit 10 of the effective capabilities) allows all ports
+    let capable = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let caps = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+            u64::from_str_radix(caps.trim(), 16).ok()
+        })
+        .is_some_and(|caps| caps & (1 << 10) != 0);
+    if capable {
+        return 0;
+    }
+    fs::read_to_string("/proc/sys/net/ipv4/ip_unprivileged_port_start")
+        .ok()
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(1024)
+}
+
+/// Warns about local ports the forward might not be allowed to listen on, unless explicitly
+/// allowed. Only a warning, as e.g. macOS allows them as well, kubectl's bind error decides.
+fn warn_privileged_ports(ports: &HashMap<u16, u16>, allow: bool) {
+    if allow {
+        return;
+    }
+    let start = unprivileged_port_start();
+    let mut privileged = ports
+        .values()
+        .copied()
+        .filter(|port| *port != 0 && *port < start)
+        .collect::<Vec<_>>();
+    if privileged.is_empty() {
+        return;
+    }
+    privileged.sort();
+    eprintln!(
+        "Warning: local ports below {start} may need elevated privileges: {} \
+         (pass --allow-privileged to silence this)",
+        privileged
+            .iter()
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+/// First local port from the given one on which nothing is listening yet
+fn free_port_from(port: u16) -> u16 {
+    (port..=port.saturating_add(100))
+        .find(|p| TcpListener::bind((Ipv4Addr::LOCALHOST, *p)).is_ok())
+        .unwrap_or(port)
 }
 
 fn select_local_ports(
     theme: &dyn Theme,
-    selected_ports: &Vec<u16>,
+    options: PromptOptions,
+    selected_ports: &[u16],
     service_ports: &HashMap<u16, u16>,
-) -> Result<HashMap<u16, u16>> {
+    other_ports: &HashMap<u16, u16>,
+) -> Result<(HashMap<u16, u16>, Option<IpAddr>)> {
     let mut ports = HashMap::new();
-    for port in selected_ports {
-        let mut prompt = Input::<u16>::with_theme(theme)
-            .with_prompt(format!("Forward container port {} to local port:", port));
-        if service_ports.contains_key(port) {
-            let default_local_port = service_ports[port];
-            prompt = prompt.default(default_local_port);
-        }
-        let local_port = prompt.interact()?;
+    let mut address = None;
+    let mut to_prompt = selected_ports
+        .iter()
+        .filter(|p| !service_ports.contains_key(p))
+        .copied()
+        .collect::<Vec<_>>();
+
+    // Offer to reuse the remembered mappings instead of prompting each port
+    let remembered = selected_ports
+        .iter()
+        .filter_map(|p| service_ports.get(p).map(|local| (*p, *local)))
+        .collect::<Vec<_>>();
+    if !remembered.is_empty() {
+        let items = remembered
+            .iter()
+            .map(|(remote, local)| format!("{remote} → {local}"))
+            .collect::<Vec<_>>();
+        eprintln!("Remembered mappings (container → local):");
+        for item in &items {
+            eprintln!("  {item}");
+        }
+        let keep_all = options.select_first
+            || options.strict
+            || Confirm::with_theme(theme)
+                .with_prompt("Use these mappings?")
+                .default(true)
+                .interact()?;
+        let to_change = if keep_all {
+            vec![]
+        } else {
+            MultiSelect::with_theme(theme)
+                .with_prompt("Select mappings to change")
+                .items(&items)
+                .interact()?
+        };
+        for (i, (remote, local)) in remembered.iter().enumerate() {
+            if to_change.contains(&i) {
+                to_prompt.push(*remote);
+            } else {
+                ports.insert(*remote, *local);
+            }
+        }
+    }
+
+    for port in selected_ports.iter().filter(|p| to_prompt.contains(p)) {
+        // NOTE: Without a prompt, never silently apply the mapping of another service
+        let local_port = if options.select_first || options.strict {
+            service_ports.get(port).copied().unwrap_or(*port)
+        } else {
+            // Suggest the local port used for the same remote port of another service
+            let default_local_port = service_ports
+                .get(port)
+                .copied()
+                .or_else(|| other_ports.get(port).map(|p| free_port_from(*p)));
+            let mut prompt = Input::<LocalPort>::with_theme(theme)
+                .with_prompt(format!("Forward container port {} to local port:", port));
+            if let Some(default_local_port) = default_local_port {
+                prompt = prompt.default(LocalPort {
+                    address: None,
+                    port: default_local_port,
+                });
+            }
+            let local_port = prompt.interact()?;
+            // NOTE: kubectl has a single set of addresses for all ports of a forward
+            match (address, local_port.address) {
+                (Some(a), Some(b)) if a != b => {
+                    return Err(MainError::ConflictingAddresses(a, b));
+                }
+                (_, Some(b)) => address = Some(b),
+                _ => {}
+            }
+            local_port.port
+        };
         ports.entry(*port).insert_entry(local_port);
     }
-    Ok(ports)
+    Ok((ports, address))
+}
+
+/// Warns if no pod backs the service, optionally waiting until one is ready
+fn check_endpoints(
+    kubectl_config: &KubectlConfig,
+    namespace: &Namespace,
+    service: &Service,
+    wait: bool,
+) -> Result<()> {
+    let lookup = || {
+        service::endpoints(
+            kubectl_config,
+            &namespace.metadata.name,
+            &service.metadata.name,
+        )
+    };
+    // NOTE: Skip the check if the endpoints can't be looked up, kubectl will tell
+    if lookup().map_or(true, |e| e.is_ready()) {
+        return Ok(());
+    }
+    if !wait {
+        eprintln!(
+            "Warning: service {service} has no ready endpoints, the forward will likely fail"
+        );
+        return Ok(());
+    }
+
+    let spinner = spinner(format!("Waiting for a ready endpoint of {service}..."));
+    while !lookup()?.is_ready() {
+        thread::sleep(ENDPOINTS_POLL_INTERVAL);
+    }
+    spinner.finish_and_clear();
+    Ok(())
+}
+
+/// Resolves the pod of `--job` (or of the newest job of `--cronjob`), preferring a running one,
+/// together with a stand-in service exposing its container ports
+fn job_pod(
+    kubectl_config: &KubectlConfig,
+    namespace: &Namespace,
+    job: Option<&str>,
+    cronjob: Option<&str>,
+) -> Result<Option<(Service, String)>> {
+    let name = &namespace.metadata.name;
+    let job = match (job, cronjob) {
+        (Some(job), _) => job.to_owned(),
+        (None, Some(cronjob)) => {
+            let jobs = kubectl::job::of_cronjob(kubectl_config, name, cronjob)?;
+            let Some(job) = jobs.first() else {
+                return Err(MainError::NoCronJobRun(cronjob.to_owned(), name.to_owned()));
+            };
+            if jobs.len() > 1 {
+                eprintln!(
+                    "Using {job}, the newest of {} jobs of cronjob {cronjob}",
+                    jobs.len()
+                );
+            }
+            job.to_owned()
+        }
+        (None, None) => return Ok(None),
+    };
+
+    let mut pods = kubectl::job::pods(kubectl_config, name, &job)?;
+    pods.sort_by(|a, b| {
+        (a.phase() == "Running", &a.metadata.creation_timestamp)
+            .cmp(&(b.phase() == "Running", &b.metadata.creation_timestamp))
+    });
+    let Some(pod) = pods.pop() else {
+        return Err(MainError::NoJobPod(job, name.to_owned()));
+    };
+    // NOTE: Job pods are short-lived, kubectl can only forward to running ones
+    if pod.phase() != "Running" {
+        eprintln!(
+            "Warning: pod {} of job {job} is {}, the forward will likely fail",
+            pod.metadata.name,
+            pod.phase()
+        );
+    }
+    // Remember the ports under the cronjob, its jobs get new names on every run
+    let service = pod.as_service(
+        cronjob.unwrap_or(&job),
+        name,
+        (kubectl::job::POD_LABEL, &job),
+    );
+    Ok(Some((service, pod.metadata.name)))
+}
+
+/// Resolves a ready pod behind the service to forward the target ports of directly,
+/// `None` (forwarding through the service) if that's not possible
+fn target_pod(
+    kubectl_config: &KubectlConfig,
+    namespace: &Namespace,
+    service: &Service,
+    ports: &HashMap<u16, u16>,
+) -> Result<Option<String>> {
+    let endpoints = service::endpoints(
+        kubectl_config,
+        &namespace.metadata.name,
+        &service.metadata.name,
+    )?;
+    let Some(pod) = endpoints.ready_pods().into_iter().next() else {
+        eprintln!("Warning: service {service} has no ready pod, forwarding through the service");
+        return Ok(None);
+    };
+
+    // Named target ports are passed on by name, they must exist in the pod
+    let names = service
+        .spec
+        .ports
+        .iter()
+        .filter(|p| ports.contains_key(&p.port))
+        .filter_map(|p| match &p.target_port {
+            Some(TargetPort::Name(name)) => Some(name),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !names.is_empty() {
+        let resolved = kubectl::pod::get(kubectl_config, &namespace.metadata.name, &pod)?;
+        if let Some(name) = names.into_iter().find(|n| !resolved.has_port_named(n)) {
+            return Err(MainError::UnknownPortName(name.to_owned(), pod));
+        }
+    }
+    eprintln!("Warning: forwarding to pod {pod} directly, bypassing the service's load balancing");
+    Ok(Some(pod))
+}
+
+/// Warns (once) that the selections couldn't be saved, e.g. to a read-only mounted config,
+/// and carries on as with `--ephemeral`
+fn warn_unsaved(filename: &Path, result: io::Result<()>) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if let Err(e) = result
+        && !WARNED.swap(true, Ordering::Relaxed)
+    {
+        eprintln!(
+            "Warning: could not save the selections to {}: {e}",
+            filename.display()
+        );
+    }
+}
+
+/// Prints a line without garbling the status line
+fn log(status: &ProgressBar, message: impl Display) {
+    status.suspend(|| eprintln!("{message}"));
+}
+
+/// Listens for `r` + Enter on the terminal, which requests restarting the forward
+fn restart_requests() -> Option<Receiver<()>> {
+    // NOTE: Line based on purpose, a raw terminal would stay raw if kpfr exits mid-read
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(|l| l.ok()) {
+            if line.trim() == "r" && sender.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    Some(receiver)
+}
+
+/// Watches the running forward until it is stopped, reconnecting it if requested
+fn supervise(
+    args: &Args,
+    config_dir: &Path,
+    forward: &mut ForwardHandle,
+    running: &AtomicBool,
+    status: &ProgressBar,
+    restarts: Option<&Receiver<()>>,
+) -> Result<()> {
+    let mut health = args.health_check.then(HealthCheck::new);
+    let mut keepalive = args.keepalive.map(Keepalive::new);
+    let max_reconnects = args
+        .max_reconnects
+        .or(args.reconnect.then_some(forward::DEFAULT_MAX_RECONNECTS));
+    let mut reconnect_at = None;
+    let mut reported_ready = None;
+    while running.load(Ordering::Relaxed) {
+        status.set_message(forward.status_line());
+        status.tick();
+
+        // Track and tell wrappers where to connect once kubectl listens (again, after
+        // reconnects, which may assign other local ports)
+        if reported_ready != Some(forward.reconnects)
+            && let Some(ports) = forward.bound_ports()
+        {
+            if let Err(e) = registry::register(config_dir, &forward.service_key(), &ports) {
+                log(status, format!("Could not track the forward: {e}"));
+            }
+            if args.print_ready_json {
+                // NOTE: Keep forwarding even if nobody reads the output anymore
+                let _ = cli::write_stdout(forward.ready_json(&ports));
+            }
+            reported_ready = Some(forward.reconnects);
+        }
+
+        if let Some(duration) = args.duration
+            && forward.started.elapsed() >= duration
+        {
+            log(
+                status,
+                format!(
+                    "Forward duration of {} elapsed, stopping...",
+                    cli::format_duration(duration)
+                ),
+            );
+            break;
+        }
+
+        // Reconnect (with backoff) when kubectl exits, e.g. because the pod is gone
+        if let Some(at) = reconnect_at {
+            if Instant::now() >= at {
+                forward.restart()?;
+                reconnect_at = None;
+            }
+        } else {
+            match forward.exited()? {
+                None => {}
+                // NOTE: kubectl receives the Ctrl-C as well, that's no failure
+                Some(_) if !running.load(Ordering::Relaxed) => break,
+                Some(exit_status) => {
+                    let Some(max_reconnects) = max_reconnects else {
+                        return Err(MainError::ForwardExited(forward.last_error()));
+                    };
+                    if forward.reconnects >= max_reconnects {
+                        return Err(MainError::ReconnectsExhausted(
+                            forward.reconnects,
+                            forward.last_error(),
+                        ));
+                    }
+                    let backoff = forward::reconnect_backoff(forward.reconnects + 1);
+                    log(
+                        status,
+                        format!("Port-forward exited ({exit_status}), reconnecting..."),
+                    );
+                    if args.verbose {
+                        log(
+                            status,
+                            format!(
+                                "Reconnect attempt {}/{max_reconnects} in {}",
+                                forward.reconnects + 1,
+                                cli::format_duration(backoff)
+                            ),
+                        );
+                    }
+                    reconnect_at = Some(Instant::now() + backoff);
+                }
+            }
+        }
+
+        // Restart on request, e.g. when the tunnel went stale after a pod restart
+        if restarts.is_some_and(|r| r.try_recv().is_ok()) {
+            log(status, "Restarting forward...");
+            forward.restart()?;
+            reconnect_at = None;
+        }
+
+        // Restart half-broken tunnels which kubectl doesn't notice itself
+        if let Some(health) = &mut health
+            && health.due()
+        {
+            if let Some(healthy) = health.check(&forward.local_addresses())
+                && args.verbose
+            {
+                log(
+                    status,
+                    format!(
+                        "Health check: forward is {}",
+                        if healthy { "healthy" } else { "unhealthy" }
+                    ),
+                );
+            }
+            if health.failures >= forward::HEALTH_CHECK_MAX_FAILURES {
+                log(
+                    status,
+                    format!(
+                        "Forward failed {} health checks in a row, reconnecting...",
+                        health.failures
+                    ),
+                );
+                forward.restart()?;
+                health.reset();
+            }
+        }
+
+        // Keep idle tunnels from being closed by e.g. load balancers or NATs
+        if let Some(keepalive) = &mut keepalive {
+            for address in keepalive.ping_due(&forward.local_addresses()) {
+                if args.verbose {
+                    log(status, format!("Keepalive: {address} is not reachable"));
+                }
+            }
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+fn shutdown(forward: &mut ForwardHandle) -> Result<()> {
+    forward.stop()?;
+    eprintln!(
+        "\nPort-forward terminated successfully after {} ({} reconnects).",
+        cli::format_duration(forward.started.elapsed()),
+        forward.reconnects
+    );
+    Ok(())
+}
+
+/// Prints the build information useful for bug reports
+fn print_version() {
+    let kubectl = match kubectl::version::client() {
+        Ok(version) => version,
+        Err(KubectlError::IO(e)) if e.kind() == io::ErrorKind::NotFound => {
+            String::from("not found")
+        }
+        Err(e) => format!("unknown ({e})"),
+    };
+    cli::print_stdout(format!(
+        "kpfr {} ({})\nkubectl: {kubectl}",
+        env!("CARGO_PKG_VERSION"),
+        env!("KPFR_GIT_SHA")
+    ));
+}
+
+/// Opens the config in the user's editor and checks it still parses afterwards
+fn edit_config(filename: &Path) -> Result<()> {
+    if !filename.exists() {
+        if let Some(dir) = filename.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // NOTE: An empty TOML file is an empty config, JSON needs the braces
+        fs::write(
+            filename,
+            match ConfigFormat::of(filename) {
+                ConfigFormat::Json => "{}\n",
+                ConfigFormat::Toml => "",
+            },
+        )?;
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+    // NOTE: The editor may come with arguments (e.g. `code --wait`)
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = process::Command::new(program)
+        .args(parts)
+        .arg(filename)
+        .status()?;
+    if !status.success() {
+        return Err(MainError::EditorFailed(editor));
+    }
+    DefaultSelections::check(&filename)
 }
 
 fn fail(e: MainError) -> ExitCode {
-    eprintln!("{e}");
+    if e.is_cancelled() {
+        // NOTE: The prompt may have been interrupted while hiding the cursor
+        let _ = console::Term::stderr().show_cursor();
+        return ExitCode::from(130);
+    }
+    match OUTPUT_FORMAT.get().copied().unwrap_or_default() {
+        OutputFormat::Human | OutputFormat::Table => eprintln!("{e}"),
+        OutputFormat::Json => eprintln!("{}", e.to_json()),
+        OutputFormat::Yaml => eprint!("{}", yaml::to_string(&e.to_json())),
+    }
     ExitCode::FAILURE
 }
 
 fn main() -> ExitCode {
-    let theme = dialoguer::theme::ColorfulTheme::default();
-    let config_dir = dirs::config_dir().unwrap().join(env!("CARGO_PKG_NAME"));
-    if !fs::exists(&config_dir).unwrap() {
+    let mut args = match Args::parse()
+        .and_then(Args::with_env)
+        .map_err(MainError::InvalidArguments)
+    {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let _ = OUTPUT_FORMAT.set(args.output);
+    if args.help {
+        cli::print_stdout(cli::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    if args.version {
+        print_version();
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(shell) = &args.completions {
+        return match cli::completion_script(shell) {
+            Ok(script) => {
+                cli::print_stdout(script);
+                ExitCode::SUCCESS
+            }
+            Err(e) => fail(e.into()),
+        };
+    }
+
+    let theme = build_theme(args.no_color);
+    let mut kubectl_config = KubectlConfig {
+        context: None,
+        impersonate: args.impersonate.clone(),
+        impersonate_groups: args.impersonate_groups.clone(),
+        insecure_skip_tls_verify: args.insecure_skip_tls_verify,
+        request_timeout: args.request_timeout,
+        list_args: match args.pass_to {
+            PassTo::List | PassTo::All => args.kubectl_args.clone(),
+            PassTo::Forward => vec![],
+        },
+        forward_args: match args.pass_to {
+            PassTo::Forward | PassTo::All => args.kubectl_args.clone(),
+            PassTo::List => vec![],
+        },
+    };
+    if kubectl_config.insecure_skip_tls_verify {
+        eprintln!("WARNING: TLS certificate verification is disabled for all kubectl calls");
+    }
+    if args.verbose
+        && (kubectl_config.impersonate.is_some() || !kubectl_config.impersonate_groups.is_empty())
+    {
         eprintln!(
-            "Creating config directory {}",
-            config_dir.to_str().unwrap_or("<unknown>")
+            "Impersonating user {} and groups [{}]",
+            kubectl_config.impersonate.as_deref().unwrap_or("-"),
+            kubectl_config.impersonate_groups.join(", ")
         );
-        fs::create_dir_all(&config_dir).unwrap();
     }
-    let filename = config_dir.join("config.json");
-    let defaults = DefaultSelections::read(&filename);
+    if args.verbose {
+        eprintln!("Using kubectl at {}", kubectl::binary().display());
+    }
+    if args.verbose
+        && let Ok(kubeconfig) = std::env::var("KUBECONFIG")
+    {
+        eprintln!("Using kubeconfig from KUBECONFIG: {kubeconfig}");
+    }
+    let Some(config_dir) = dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME"))) else {
+        return fail(MainError::NoConfigDir);
+    };
+    let filename = selection::config_file(&config_dir, args.config_format);
+
+    if args.print_config_path {
+        cli::print_stdout(filename.display());
+        return ExitCode::SUCCESS;
+    }
+    if args.command == Some(Subcommand::ConfigEdit) {
+        return match edit_config(&filename) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => fail(e),
+        };
+    }
+
+    if let Some(Subcommand::ConfigForget(service)) = &args.command {
+        return match selection::forget_ports(&filename, service.as_deref()) {
+            Ok(forgotten) if forgotten.is_empty() => fail(MainError::NotRemembered(
+                service.clone().unwrap_or_else(|| String::from("*")),
+            )),
+            Ok(forgotten) => {
+                for key in forgotten {
+                    cli::print_stdout(format!("Forgot the ports of {key}"));
+                }
+                ExitCode::SUCCESS
+            }
+            Err(e) => fail(e.into()),
+        };
+    }
+
+    // Complete remembered services (used by the shell completion, must not hit the cluster)
+    if args.complete_services {
+        let defaults = DefaultSelections::read(&filename).ok().flatten();
+        for service in defaults.map(|d| d.known_services()).unwrap_or_default() {
+            cli::print_stdout(service);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // NOTE: Fail instead of starting over, saving would lose everything in the config
+    let defaults = match DefaultSelections::read(&filename) {
+        Ok(defaults) => defaults,
+        Err(e) => return fail(e),
+    };
+
+    // Go straight to the last forwarded service
+    if (args.reuse || args.interactive_ports_only)
+        && args.service.is_none()
+        && let Some(defaults) = &defaults
+    {
+        args.service = defaults.last_service.as_ref().map(|service| {
+            match (&defaults.namespace, service.contains('/')) {
+                (Some(namespace), false) => format!("{namespace}/{service}"),
+                _ => service.to_owned(),
+            }
+        });
+    }
+
+    // Check the remembered config against the cluster instead of forwarding
+    if args.command == Some(Subcommand::Validate) {
+        let Some(defaults) = &defaults else {
+            eprintln!("Nothing remembered yet");
+            return ExitCode::SUCCESS;
+        };
+        return match validate::run(&kubectl_config, defaults) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => fail(e),
+        };
+    }
+    let ports = if let Some(ports) = &args.ports {
+        Some(ports.to_owned())
+    } else if let Some(prefix) = &args.ports_from_env {
+        match cli::port_mappings_from_env(prefix) {
+            Ok(ports) => Some(ports),
+            Err(e) => return fail(e.into()),
+        }
+    } else if args.stdin_ports {
+        match cli::port_mappings_from_reader(io::stdin().lock()) {
+            Ok(ports) => Some(ports),
+            Err(e) => return fail(e.into()),
+        }
+    } else {
+        None
+    };
+    let ports_file = match args.ports_file.as_ref().map(selection::read_port_mappings) {
+        Some(Ok(mappings)) => Some(mappings),
+        Some(Err(e)) => return fail(e),
+        None => None,
+    };
+    let prompt_options = PromptOptions {
+        fuzzy: !args.no_fuzzy
+            && defaults
+                .as_ref()
+                .and_then(|d| d.settings.fuzzy)
+                .unwrap_or(true),
+        select_first: args.select_first,
+        display_column: args.display_column.as_deref(),
+        verbose: args.verbose,
+        strict: args.strict,
+        live_picker: args.live_picker,
+        auto_select_single_port: !args.confirm_single_port
+            && defaults
+                .as_ref()
+                .and_then(|d| d.settings.auto_select_single_port)
+                .unwrap_or(true),
+        context_sort: args
+            .context_sort
+            .or(defaults.as_ref().and_then(|d| d.settings.context_sort))
+            .unwrap_or_default(),
+        recent_contexts: defaults
+            .as_ref()
+            .and_then(|d| d.recent_contexts.as_deref())
+            .unwrap_or_default(),
+        show_counts: defaults
+            .as_ref()
+            .and_then(|d| d.settings.show_counts)
+            .unwrap_or(true),
+    };
+
+    // Stay in the context of the last forward, without switching kubectl's current context
+    let remembered_context = match remembered_context(&args, prompt_options.recent_contexts) {
+        Some(ctx) => match context::get() {
+            Ok(contexts) if contexts.contains(&ctx) => Some(ctx),
+            Ok(_) => return fail(MainError::ContextNotFound(ctx)),
+            Err(e) => return fail(e.into()),
+        },
+        None => None,
+    };
 
     // Select context if more than one are available
-    if let Err(e) = preselect_context(&theme) {
+    if remembered_context.is_none()
+        && let Err(e) = preselect_context(
+            &*theme,
+            prompt_options,
+            args.context.as_deref(),
+            args.context_glob.as_deref(),
+            args.interactive,
+            args.no_context_select
+                || args.reuse
+                || args.interactive_ports_only
+                || defaults
+                    .as_ref()
+                    .and_then(|d| d.settings.skip_context)
+                    .unwrap_or(false),
+        )
+    {
+        return fail(e);
+    }
+
+    // Only switch the context
+    if args.context_only {
+        return match context::current() {
+            Ok(ctx) => {
+                eprintln!("Switched to context '{ctx}'");
+                ExitCode::SUCCESS
+            }
+            Err(e) => fail(e.into()),
+        };
+    }
+
+    // Pin the selected context, so switching it elsewhere doesn't affect this forward
+    kubectl_config.context = remembered_context.or_else(|| context::current().ok());
+
+    // Fail early and clearly if e.g. the VPN is down, instead of at the namespace listing
+    match kubectl::check_cluster(&kubectl_config) {
+        Ok(()) => {}
+        Err(KubectlError::Unreachable(detail)) => {
+            let context = kubectl_config.context.clone().unwrap_or_default();
+            return fail(MainError::ClusterUnreachable(context, detail));
+        }
+        Err(e) => return fail(e.into()),
+    }
+
+    // Show which cluster is actually targeted
+    if args.verbose || args.show_context {
+        match context::describe(&kubectl_config) {
+            Ok(info) => eprintln!("Context: {info}"),
+            Err(e) => eprintln!("Could not resolve context details: {e}"),
+        }
+    }
+
+    // Guard against accidental forwards to e.g. production, before remembering anything
+    if let Err(e) = confirm_protected_context(
+        &*theme,
+        defaults
+            .as_ref()
+            .map(|d| d.settings.protected_contexts.as_slice())
+            .unwrap_or_default(),
+        kubectl_config.context.as_deref(),
+        args.strict,
+    ) {
         return fail(e);
     }
 
-    // Select namespace
+    // Select namespace (an empty selector clears the remembered one)
     let default_namespace = defaults.clone().and_then(|d| d.namespace);
-    let namespace = match select_namespace(&theme, default_namespace) {
-        Ok(n) => n,
-        Err(e) => return fail(e),
+    let namespace_selector = match &args.namespace_selector {
+        Some(selector) => Some(selector.to_owned()).filter(|s| !s.is_empty()),
+        None => defaults.clone().and_then(|d| d.namespace_selector),
+    };
+    let given_namespace = given_namespace(&args);
+    let namespace = if args.all_namespaces || !args.namespaces.is_empty() {
+        None
+    } else if let Some(namespace) = given_namespace {
+        // `<namespace>/<service>` or `--namespace` determine the namespace already
+        if let Err(e) = kubectl::validate_name(&namespace.metadata.name) {
+            return fail(e.into());
+        }
+        Some(namespace)
+    } else {
+        if args.namespace_selector.is_none()
+            && let Some(selector) = &namespace_selector
+        {
+            eprintln!("Filtering namespaces by remembered selector '{selector}'");
+        }
+        match select_namespace(
+            &*theme,
+            prompt_options,
+            &kubectl_config,
+            namespace_selector.as_deref(),
+            default_namespace,
+        ) {
+            Ok(n) => Some(n),
+            Err(e) => return fail(e),
+        }
     };
-    let selection = Selection::from_defaults(&namespace, &defaults);
 
-    // Select service
-    let default_service = defaults.clone().and_then(|d| d.last_service);
-    let service = match select_service(&theme, &namespace, default_service) {
-        Ok(s) => s,
-        Err(e) => return fail(e),
+    // Pick from the services of several namespaces at once
+    let namespaces = args
+        .namespaces
+        .iter()
+        .map(|n| Namespace::named(n))
+        .collect::<Vec<_>>();
+    for namespace in &namespaces {
+        if let Err(e) = kubectl::validate_name(&namespace.metadata.name) {
+            return fail(e.into());
+        }
+    }
+
+    // Select service (resolving a friendly alias to the actual service name)
+    let requested_service = args.service.as_deref().map(|s| match &defaults {
+        Some(d) => d.resolve_alias(namespace.as_ref().map(|n| n.metadata.name.as_str()), s),
+        None => s.to_owned(),
+    });
+    let job = match (&args.job, &args.cronjob, &namespace) {
+        (None, None, _) => None,
+        (_, _, None) => return fail(MainError::NoNamespace),
+        (job, cronjob, Some(namespace)) => {
+            match job_pod(
+                &kubectl_config,
+                namespace,
+                job.as_deref(),
+                cronjob.as_deref(),
+            ) {
+                Ok(job) => job,
+                Err(e) => return fail(e),
+            }
+        }
+    };
+    let (service, job_pod) = match job {
+        Some((service, pod)) => (service, Some(pod)),
+        None => match select_service(
+            &*theme,
+            prompt_options,
+            &kubectl_config,
+            match namespace.as_ref() {
+                Some(namespace) => slice::from_ref(namespace),
+                None => &namespaces,
+            },
+            args.service_type.as_deref(),
+            requested_service.as_deref(),
+            defaults.as_ref(),
+        ) {
+            Ok(s) => (s, None),
+            Err(e) => return fail(e),
+        },
+    };
+
+    // Guard against picking e.g. the `kubernetes` service by mistake
+    if requested_service.is_none()
+        && job_pod.is_none()
+        && let Err(e) = confirm_system_service(
+            &*theme,
+            prompt_options,
+            defaults
+                .as_ref()
+                .and_then(|d| d.settings.confirm_services.as_deref()),
+            &service,
+        )
+    {
+        return fail(e);
+    }
+
+    // Forward within the namespace the service actually lives in
+    let Some(namespace) = service.namespace().or(namespace) else {
+        return fail(MainError::NoNamespace);
     };
+
+    // Let later kubectl calls in the shell use the namespace as well
+    if args.set_default_namespace {
+        match context::set_namespace(kubectl_config.context.as_deref(), &namespace.metadata.name) {
+            Ok(()) => eprintln!("Set namespace '{namespace}' as the kubectl default"),
+            Err(e) => eprintln!("Could not set the default namespace: {e}"),
+        }
+    }
+
+    // kubectl picks an arbitrary backing pod for headless/selectorless services
+    if service.spec.is_headless() || service.spec.is_selectorless() {
+        let kind = if service.spec.is_headless() {
+            "headless"
+        } else {
+            "selectorless"
+        };
+        eprintln!("Note: {service} is a {kind} service, kubectl forwards to a single backing pod");
+        match service::endpoints(
+            &kubectl_config,
+            &namespace.metadata.name,
+            &service.metadata.name,
+        ) {
+            Ok(endpoints) if !endpoints.ready_pods().is_empty() => {
+                eprintln!("      picked from: {}", endpoints.ready_pods().join(", "))
+            }
+            Ok(_) => {}
+            Err(e) if args.verbose => eprintln!("Could not look up endpoints: {e}"),
+            Err(_) => {}
+        }
+    }
+    if args.describe {
+        let endpoints = service::endpoints(
+            &kubectl_config,
+            &namespace.metadata.name,
+            &service.metadata.name,
+        );
+        if let Err(e) = &endpoints
+            && args.verbose
+        {
+            eprintln!("Could not look up endpoints: {e}");
+        }
+        summary::print_description(&service, endpoints.ok().as_ref());
+    }
+
+    let mut selection = Selection::from_defaults(&namespace, &defaults);
+    selection.namespace_selector = namespace_selector;
+    if let Some(context) = &kubectl_config.context {
+        selection.record_context(context);
+    }
     let mut selection = selection.set_last_service(&service);
+    let changed_ports = selection.record_service_ports(&service);
+    if args.reuse
+        && let Some(previous) = &changed_ports
+    {
+        if previous.is_empty() {
+            eprintln!("No remembered state of {service} yet, selecting the ports");
+        } else {
+            let list = |ports: &[u16]| {
+                ports
+                    .iter()
+                    .map(u16::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            eprintln!(
+                "Service {service} changed since the last run (ports {} → {}), selecting the ports again",
+                list(previous),
+                list(&selection.service_ports[&service.key()])
+            );
+        }
+    }
+
+    // Local ports used for the same remote ports of other services, as a hint
+    let mut other_mappings = selection
+        .ports
+        .iter()
+        .filter(|(key, _)| **key != service.key() && **key != service.metadata.name)
+        .collect::<Vec<_>>();
+    other_mappings.sort_by_key(|(key, _)| *key);
+    let other_ports = other_mappings
+        .into_iter()
+        .flat_map(|(_, mapping)| mapping.iter().map(|(remote, local)| (*remote, *local)))
+        .collect::<HashMap<_, _>>();
 
     // Get default ports for the selected service
     let default_ports = selection.ports_for(&service);
 
-    // Select remote ports from service
-    let remote_ports = match select_remote_ports(&theme, &service, default_ports) {
-        Ok(p) => p,
-        Err(e) => return fail(e),
-    };
+    // Use the mappings given on the command line or of the ports file if it covers the service
+    let file_ports = ports_file.as_ref().and_then(|f| {
+        f.get(&service.key())
+            .or_else(|| f.get(&service.metadata.name))
+    });
+    let reused_ports = (args.reuse && changed_ports.is_none() && !default_ports.is_empty())
+        .then(|| default_ports.clone());
+    let file_ports = file_ports.or(reused_ports.as_ref());
+    let (ports_mapping, inline_address) = if let Some(mapping) = ports.as_ref().or(file_ports) {
+        if let Err(e) = validate_remote_ports(&service, mapping.keys()) {
+            return fail(e);
+        }
+        (mapping.to_owned(), None)
+    } else {
+        // Select remote ports from service
+        let remote_ports =
+            match select_remote_ports(&*theme, prompt_options, &service, Some(default_ports)) {
+                Ok(p) => p,
+                Err(e) => return fail(e),
+            };
 
-    // Abort if no ports selected
-    if remote_ports.is_empty() {
-        selection.save(&filename).unwrap();
-        eprintln!("{}", MainError::NoPorts);
-        return ExitCode::FAILURE;
-    }
+        // Abort if no ports selected
+        if remote_ports.is_empty() {
+            if !args.ephemeral {
+                warn_unsaved(&filename, selection.save(&filename));
+            }
+            return fail(MainError::NoPorts);
+        }
 
-    // Decide which local ports to map to
-    let ports_mapping = match select_local_ports(&theme, &remote_ports, default_ports) {
-        Ok(p) => p,
-        Err(e) => return fail(e),
+        // Decide which local ports to map to
+        match select_local_ports(
+            &*theme,
+            prompt_options,
+            &remote_ports,
+            default_ports,
+            &other_ports,
+        ) {
+            Ok(p) => p,
+            Err(e) => return fail(e),
+        }
     };
 
-    // Save selections to file
+    // Explain kubectl's likely "permission denied" when listening on e.g. port 80
+    warn_privileged_ports(&ports_mapping, args.allow_privileged);
+
+    // Save selections to file (the flag wins over addresses entered in the port prompt)
+    let requested_address = args.address.or(inline_address);
+    // NOTE: Listening on all interfaces exposes the service, so it's never done implicitly
+    if let Some(address) = requested_address.filter(|a| !a.is_unspecified()) {
+        selection.address = Some(address.to_string());
+    }
     selection
         .ports
-        .entry(service.metadata.name.clone())
-        .insert_entry(ports_mapping);
-    selection.save(&filename).unwrap();
+        .entry(service.key())
+        .insert_entry(ports_mapping.clone());
+    if !args.ephemeral {
+        warn_unsaved(&filename, selection.save(&filename));
+    }
 
     // Abort if no ports selected
-    if remote_ports.is_empty() {
-        eprintln!("{}", MainError::NoPorts);
-        return ExitCode::FAILURE;
+    if ports_mapping.is_empty() {
+        return fail(MainError::NoPorts);
     }
 
+    // Turn a cryptic kubectl failure into a clear message
+    if job_pod.is_none()
+        && let Err(e) = check_endpoints(&kubectl_config, &namespace, &service, args.wait)
+    {
+        return fail(e);
+    }
+
+    // Skip the service's port mapping if asked to
+    let rotate_pods = args.rotate_pod && job_pod.is_none();
+    let pod = if job_pod.is_some() {
+        job_pod
+    } else if args.use_target_port || args.rotate_pod {
+        match target_pod(&kubectl_config, &namespace, &service, &ports_mapping) {
+            Ok(pod) => pod,
+            Err(e) => return fail(e),
+        }
+    } else {
+        None
+    };
+
     // Forward ports (keeps running in subprocess)
-    let ports = selection.ports.get(&service.metadata.name).unwrap();
+    let mut ports = selection.ports[&service.key()].clone();
+    if let Err(e) = replace_running_forwards(
+        &*theme,
+        &config_dir,
+        &mut ports,
+        args.replace,
+        prompt_options.may_prompt(),
+    ) {
+        return fail(e);
+    }
+    if ports != selection.ports[&service.key()] {
+        selection.ports.insert(service.key(), ports.clone());
+        if !args.ephemeral {
+            warn_unsaved(&filename, selection.save(&filename));
+        }
+    }
+    let status = if !args.quiet && console::Term::stderr().is_term() {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
     let running = Arc::new(AtomicBool::new(true));
-    let mut forward_process = match kubectl::forward_ports(&namespace, &service, ports)
-        .map_err(MainError::KubectlFailed)
+    let address = match selection.resolve_address(&service, requested_address) {
+        Ok(a) => a,
+        Err(e) => return fail(e),
+    };
+    let spec = ForwardSpec {
+        namespace: namespace.to_owned(),
+        service: service.to_owned(),
+        ports: ports.to_owned(),
+        address,
+        pod,
+    };
+    let mut forward = match ForwardHandle::spawn(
+        &kubectl_config,
+        spec.clone(),
+        &status,
+        args.print_ready_json,
+    )
+    .map_err(MainError::KubectlFailed)
     {
-        Ok(fp) => fp,
+        Ok(f) => f,
         Err(e) => return fail(e),
     };
+    forward.rotate_pods = rotate_pods;
+
+    // Show and export the OS-assigned local ports (`0`) instead of the requested ones
+    let mut spec = spec;
+    if ports.values().any(|port| *port == 0)
+        && let Some(bound) = forward.wait_bound(forward::BIND_TIMEOUT)
+    {
+        spec.ports = bound;
+    }
+
+    // Let other local tools pick up the mappings
+    if let Some(env_file) = &args.env_file
+        && let Err(e) = dotenv::write(env_file, &dotenv::variables(&spec))
+    {
+        log(
+            &status,
+            format!("Could not write {}: {e}", env_file.display()),
+        );
+    }
+
+    if !args.quiet {
+        let hyperlinks = !args.no_hyperlinks && console::Term::stderr().is_term();
+        match args.output {
+            OutputFormat::Table => status.suspend(|| summary::print_table(&spec)),
+            OutputFormat::Yaml => status.suspend(|| summary::print_yaml(&spec)),
+            _ => status.suspend(|| summary::print(&spec, hyperlinks)),
+        }
+    }
+    if args.suggest_hosts {
+        status.suspend(|| summary::print_hosts_suggestion(&spec));
+    }
 
     // Add Ctrl-C handler to cancel/finish the port-forwarding
     let r1 = Arc::clone(&running);
     if let Err(e) = ctrlc::set_handler(move || {
-        forward_process.kill().unwrap();
-        forward_process.wait().unwrap();
-        eprintln!("\nPort-forward terminated successfully.");
         r1.store(false, Ordering::Relaxed);
     })
     .map_err(MainError::CtrlC)
     {
+        let _ = shutdown(&mut forward);
+        registry::unregister(&config_dir);
         return fail(e);
     }
 
     // Keep the main process running while forwarding process runs
-    while running.load(Ordering::Relaxed) {
-        thread::sleep(Duration::from_millis(100));
+    let restarts = (!args.stdin_ports).then(restart_requests).flatten();
+    if restarts.is_some() && !args.quiet {
+        status.suspend(|| eprintln!("Type r and press Enter to restart the forward"));
+    }
+    let mut logs = if args.logs {
+        match LogTail::spawn(&kubectl_config, &spec, &status, args.print_ready_json) {
+            Ok(Some(logs)) => Some(logs),
+            Ok(None) => {
+                log(&status, "Service has no selector, not following any logs");
+                None
+            }
+            Err(e) => {
+                log(&status, format!("Could not follow the logs: {e}"));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let result = supervise(
+        &args,
+        &config_dir,
+        &mut forward,
+        &running,
+        &status,
+        restarts.as_ref(),
+    );
+    if let Some(logs) = &mut logs {
+        let _ = logs.stop();
+    }
+    status.finish_and_clear();
+    registry::unregister(&config_dir);
+
+    // Record the session if opted in
+    if let Some(session_log) = args
+        .session_log
+        .clone()
+        .or_else(|| selection.settings.session_log.as_ref().map(PathBuf::from))
+    {
+        let record = SessionRecord::new(kubectl_config.context.clone(), &spec, &forward);
+        if let Err(e) = record.append(&session_log) {
+            eprintln!("Could not write the session log: {e}");
+        }
+    }
+    match result.and_then(|()| shutdown(&mut forward)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let _ = forward.stop();
+            fail(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dialoguer::theme::SimpleTheme;
+
+    fn service(namespace: &str, name: &str) -> Service {
+        serde_json::from_value(serde_json::json!({
+            "metadata": {"name": name, "namespace": namespace},
+            "spec": {},
+        }))
+        .unwrap()
+    }
+
+    fn options() -> PromptOptions<'static> {
+        PromptOptions {
+            fuzzy: true,
+            select_first: false,
+            display_column: None,
+            verbose: false,
+            show_counts: true,
+            strict: true,
+            live_picker: false,
+            auto_select_single_port: true,
+            context_sort: ContextSort::default(),
+            recent_contexts: &[],
+        }
+    }
+
+    fn parse_args(flags: &[&str], env: &[(&str, &str)]) -> Args {
+        Args::parse_from(flags.iter().map(|f| f.to_string()))
+            .and_then(|args| {
+                args.with_env_from(|name| {
+                    env.iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, v)| v.to_string())
+                })
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn flags_take_precedence_over_env() {
+        let env = [("KPFR_NAMESPACE", "env"), ("KPFR_PORTS", "80:8080")];
+        let args = parse_args(&["--namespace", "flag", "--ports", "80:9090"], &env);
+        assert_eq!(given_namespace(&args), Some(Namespace::named("flag")));
+        assert_eq!(args.ports, Some(HashMap::from([(80, 9090)])));
+
+        let args = parse_args(&["flag/api"], &env);
+        assert_eq!(given_namespace(&args), Some(Namespace::named("flag")));
+
+        let args = parse_args(&["--namespace", "flag"], &[("KPFR_SERVICE", "env/api")]);
+        assert_eq!(given_namespace(&args), Some(Namespace::named("flag")));
+        assert_eq!(args.service.as_deref(), Some("api"));
+    }
+
+    #[test]
+    fn env_takes_precedence_over_config() {
+        let env = [("KPFR_NAMESPACE", "env"), ("KPFR_PORTS", "80:8080")];
+        let args = parse_args(&[], &env);
+        assert_eq!(given_namespace(&args), Some(Namespace::named("env")));
+        assert_eq!(args.ports, Some(HashMap::from([(80, 8080)])));
+
+        let args = parse_args(&[], &[("KPFR_SERVICE", "env/api")]);
+        assert_eq!(given_namespace(&args), Some(Namespace::named("env")));
+    }
+
+    #[test]
+    fn config_applies_without_flags_and_env() {
+        let args = parse_args(&[], &[]);
+        assert_eq!(given_namespace(&args), None);
+        assert_eq!(args.ports, None);
     }
 
-    ExitCode::SUCCESS
+    #[test]
+    fn portless_service_has_no_ports_to_select() {
+        let result = select_remote_ports(&SimpleTheme, options(), &service("web", "mail"), None);
+        assert!(matches!(result, Err(MainError::NoServicePorts(key)) if key == "web/mail"));
+    }
+
+    fn single_port_service() -> Service {
+        serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "api", "namespace": "web"},
+            "spec": {"ports": [{"port": 80}]},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn auto_selects_single_port() {
+        let ports = select_remote_ports(&SimpleTheme, options(), &single_port_service(), None);
+        assert_eq!(ports.unwrap(), [80]);
+    }
+
+    #[test]
+    fn confirms_single_port_when_disabled() {
+        let options = PromptOptions {
+            auto_select_single_port: false,
+            ..options()
+        };
+        // NOTE: Strict mode fails instead of showing the prompt
+        let ports = select_remote_ports(&SimpleTheme, options, &single_port_service(), None);
+        assert!(matches!(ports, Err(MainError::PromptRequired(_, _))));
+
+        let remembered = HashMap::from([(80, 8080)]);
+        let ports = select_remote_ports(
+            &SimpleTheme,
+            options,
+            &single_port_service(),
+            Some(&remembered),
+        );
+        assert_eq!(ports.unwrap(), [80]);
+    }
+
+    #[test]
+    fn qualified_labels_narrow_by_namespace() {
+        let services = [
+            service("web", "api"),
+            service("prod", "api"),
+            service("prod", "db"),
+            service("staging", "api"),
+        ];
+        let labels = services
+            .iter()
+            .map(|s| service_label(s, true))
+            .collect::<Vec<_>>();
+        let matching = |query: &str| {
+            labels
+                .iter()
+                .filter(|label| picker::matches(label, query))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching("prod/api"), ["prod/api"]);
+        assert_eq!(matching("PROD/"), ["prod/api", "prod/db"]);
+        assert_eq!(matching("api"), ["web/api", "prod/api", "staging/api"]);
+
+        // NOTE: Without the namespace in the label, typing it finds nothing
+        let unqualified = service_label(&services[1], false);
+        assert!(!picker::matches(&unqualified, "prod/api"));
+    }
+
+    #[test]
+    fn single_namespace_labels_are_unqualified() {
+        assert_eq!(service_label(&service("web", "api"), false), "api");
+    }
+
+    #[test]
+    fn interactive_ports_only_stays_in_the_remembered_context() {
+        let recent = [String::from("staging"), String::from("prod")];
+        let args = parse_args(&["--interactive-ports-only"], &[]);
+        assert_eq!(
+            remembered_context(&args, &recent).as_deref(),
+            Some("staging")
+        );
+        assert_eq!(remembered_context(&args, &[]), None);
+
+        let args = parse_args(&["--interactive-ports-only", "--context", "prod"], &[]);
+        assert_eq!(remembered_context(&args, &recent), None);
+        assert_eq!(remembered_context(&parse_args(&[], &[]), &recent), None);
+    }
+
+    #[test]
+    fn never_prompting_flags_skip_the_running_forward_prompt() {
+        assert!(!options().may_prompt());
+        let select_first = PromptOptions {
+            strict: false,
+            select_first: true,
+            ..options()
+        };
+        assert!(!select_first.may_prompt());
+        let interactive = PromptOptions {
+            strict: false,
+            ..options()
+        };
+        assert!(interactive.may_prompt());
+    }
+
+    #[test]
+    fn never_picks_non_tcp_ports_implicitly() {
+        let service: Service = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "dns", "namespace": "kube-system"},
+            "spec": {"ports": [
+                {"name": "dns", "port": 53, "protocol": "UDP"},
+                {"name": "dns-tcp", "port": 53, "protocol": "TCP"},
+                {"name": "metrics", "port": 9153, "protocol": "TCP"},
+                {"name": "sctp", "port": 9999, "protocol": "SCTP"},
+            ]},
+        }))
+        .unwrap();
+        let select_first = PromptOptions {
+            select_first: true,
+            ..options()
+        };
+        let ports = select_remote_ports(&SimpleTheme, select_first, &service, None);
+        assert_eq!(ports.unwrap(), [53, 9153]);
+
+        // NOTE: Remembered ports of another protocol aren't checked by default
+        let remembered = HashMap::from([(9999, 9999), (9153, 9153)]);
+        let ports = select_remote_ports(&SimpleTheme, options(), &service, Some(&remembered));
+        assert_eq!(ports.unwrap(), [9153]);
+        assert!(matches!(
+            select_remote_ports(&SimpleTheme, options(), &service, None),
+            Err(MainError::PromptRequired(_, candidates)) if candidates == ["53", "9153"]
+        ));
+    }
+
+    #[test]
+    fn single_udp_port_is_not_forwardable() {
+        let service: Service = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "syslog", "namespace": "web"},
+            "spec": {"ports": [{"port": 514, "protocol": "UDP"}]},
+        }))
+        .unwrap();
+        assert!(matches!(
+            select_remote_ports(&SimpleTheme, options(), &service, None),
+            Err(MainError::UnsupportedProtocol(key, ports)) if key == "web/syslog" && ports == ["514/UDP"]
+        ));
+    }
 }
@@ -1,9 +1,23 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// Reads `null` like a missing field, kubectl writes e.g. the lists of an empty kubeconfig as `null`
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
 pub struct KubectlList<T> {
+    #[serde(deserialize_with = "null_as_default")]
     pub items: Vec<T>,
 }
 
@@ -11,21 +25,100 @@ pub struct KubectlList<T> {
 pub struct Namespace {
     pub metadata: Metadata,
 }
+impl PartialEq for Namespace {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata == other.metadata
+    }
+}
+impl Eq for Namespace {}
+impl Hash for Namespace {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.metadata.hash(state);
+    }
+}
+impl Namespace {
+    pub fn named(name: &str) -> Self {
+        Self {
+            metadata: Metadata {
+                name: name.to_owned(),
+                namespace: None,
+            },
+        }
+    }
+}
 impl Display for Namespace {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.metadata.name)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Metadata {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Service {
     pub metadata: Metadata,
     pub spec: ServiceSpec,
+    /// The service as returned by kubectl, for user defined display columns
+    #[serde(skip)]
+    pub raw: Value,
+}
+impl Service {
+    /// Namespace-qualified identity (`namespace/name`), used as key in the saved config
+    pub fn key(&self) -> String {
+        match &self.metadata.namespace {
+            Some(namespace) => format!("{namespace}/{}", self.metadata.name),
+            None => self.metadata.name.to_owned(),
+        }
+    }
+
+    /// Namespace the service lives in, if known
+    pub fn namespace(&self) -> Option<Namespace> {
+        self.metadata.namespace.as_deref().map(Namespace::named)
+    }
+
+    /// Value at the dotted path (e.g. `{.spec.type}` or `metadata.labels.app\.kubernetes\.io/name`)
+    /// of the raw service, `None` if it doesn't exist or isn't a scalar
+    pub fn column(&self, path: &str) -> Option<String> {
+        let path = path.trim();
+        let path = path
+            .strip_prefix('{')
+            .and_then(|p| p.strip_suffix('}'))
+            .unwrap_or(path);
+        let path = path.strip_prefix('.').unwrap_or(path);
+
+        // NOTE: Keys may contain dots themselves (like most labels), those are escaped as `\.`
+        let mut value = &self.raw;
+        for key in path.replace("\\.", "\0").split('.') {
+            let key = key.replace('\0', ".");
+            value = match value {
+                Value::Object(map) => map.get(&key)?,
+                Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        match value {
+            Value::String(s) => Some(s.to_owned()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        }
+    }
+}
+impl PartialEq for Service {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata == other.metadata
+    }
+}
+impl Eq for Service {}
+impl Hash for Service {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.metadata.hash(state);
+    }
 }
 impl Display for Service {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -33,13 +126,378 @@ impl Display for Service {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
 pub struct ServiceSpec {
+    /// Missing for e.g. some `ExternalName` services
+    #[serde(default, deserialize_with = "null_as_default")]
     pub ports: Vec<Port>,
+    #[serde(rename = "clusterIP")]
+    pub cluster_ip: Option<String>,
+    pub selector: Option<BTreeMap<String, String>>,
+    #[serde(rename = "type")]
+    pub service_type: Option<String>,
 }
+impl ServiceSpec {
+    /// Type of the service (e.g. `ClusterIP`, `LoadBalancer`), defaulting like Kubernetes does
+    pub fn service_type(&self) -> &str {
+        self.service_type.as_deref().unwrap_or("ClusterIP")
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+    /// Headless services (`clusterIP: None`) have no virtual IP balancing the pods
+    pub fn is_headless(&self) -> bool {
+        self.cluster_ip.as_deref() == Some("None")
+    }
+
+    /// Services without selector are backed by manually managed endpoints
+    pub fn is_selectorless(&self) -> bool {
+        self.selector.as_ref().is_none_or(|s| s.is_empty())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct Port {
     pub port: u16,
+    pub name: Option<String>,
+    /// `TCP`, `UDP` or `SCTP`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// Application protocol declared by the service (e.g. `http`, `grpc`, `kubernetes.io/h2c`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_protocol: Option<String>,
+    /// Port of the pods the traffic goes to, by number or by container port name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_port: Option<TargetPort>,
+}
+impl Port {
+    /// Protocol of the port, defaulting like Kubernetes does
+    pub fn protocol(&self) -> &str {
+        self.protocol.as_deref().unwrap_or("TCP")
+    }
+
+    /// Numeric port of the pods, which is the service port unless set otherwise,
+    /// `None` for named target ports
+    pub fn numeric_target_port(&self) -> Option<u16> {
+        match &self.target_port {
+            None => Some(self.port),
+            Some(TargetPort::Number(port)) => Some(*port),
+            Some(TargetPort::Name(_)) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum TargetPort {
+    Number(u16),
+    Name(String),
+}
+impl Display for TargetPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(port) => write!(f, "{port}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Endpoints {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub subsets: Vec<EndpointSubset>,
+}
+impl Endpoints {
+    /// Whether any address is ready to receive traffic
+    pub fn is_ready(&self) -> bool {
+        self.subsets.iter().any(|s| !s.addresses.is_empty())
+    }
+
+    /// Names of the pods backing the ready addresses
+    pub fn ready_pods(&self) -> Vec<String> {
+        self.subsets
+            .iter()
+            .flat_map(|s| &s.addresses)
+            .filter_map(|a| a.target_ref.as_ref())
+            .filter(|r| r.kind.as_deref() == Some("Pod"))
+            .map(|r| r.name.to_owned())
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EndpointSubset {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub addresses: Vec<EndpointAddress>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointAddress {
+    pub target_ref: Option<ObjectReference>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ObjectReference {
+    pub kind: Option<String>,
+    pub name: String,
+}
+
+/// Metadata of the objects found through their owner (jobs and their pods)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedMetadata {
+    pub name: String,
+    /// RFC 3339 in UTC, so it sorts like the time
+    pub creation_timestamp: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub owner_references: Vec<ObjectReference>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Job {
+    pub metadata: OwnedMetadata,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Pod {
+    pub metadata: OwnedMetadata,
+    pub spec: PodSpec,
+    pub status: Option<PodStatus>,
+}
+impl Pod {
+    /// Whether any container declares a port of the name
+    pub fn has_port_named(&self, name: &str) -> bool {
+        self.spec
+            .containers
+            .iter()
+            .flat_map(|c| &c.ports)
+            .any(|p| p.name.as_deref() == Some(name))
+    }
+
+    /// Lifecycle phase (e.g. `Running`, `Succeeded`), as reported by the cluster
+    pub fn phase(&self) -> &str {
+        self.status
+            .as_ref()
+            .and_then(|s| s.phase.as_deref())
+            .unwrap_or("Unknown")
+    }
+
+    /// Stand-in service exposing the container ports of the pod, so a job's pod can be
+    /// selected and remembered like a service (named after the job, selecting its pods)
+    pub fn as_service(&self, name: &str, namespace: &str, selector: (&str, &str)) -> Service {
+        let mut ports = Vec::<Port>::new();
+        for port in self.spec.containers.iter().flat_map(|c| &c.ports) {
+            if !ports.iter().any(|p| p.port == port.container_port) {
+                ports.push(Port {
+                    port: port.container_port,
+                    name: port.name.to_owned(),
+                    protocol: port.protocol.to_owned(),
+                    app_protocol: None,
+                    target_port: None,
+                });
+            }
+        }
+        let (label, value) = selector;
+        Service {
+            metadata: Metadata {
+                name: name.to_owned(),
+                namespace: Some(namespace.to_owned()),
+            },
+            spec: ServiceSpec {
+                ports,
+                cluster_ip: None,
+                selector: Some(BTreeMap::from([(label.to_owned(), value.to_owned())])),
+                service_type: None,
+            },
+            raw: Value::Null,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PodSpec {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub containers: Vec<Container>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Container {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub ports: Vec<ContainerPort>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerPort {
+    pub container_port: u16,
+    pub name: Option<String>,
+    pub protocol: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PodStatus {
+    pub phase: Option<String>,
+}
+
+/// Subset of `kubectl config view --minify --output=json`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct KubeConfig {
+    #[serde(default)]
+    pub current_context: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub contexts: Vec<NamedContext>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub clusters: Vec<NamedCluster>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NamedContext {
+    pub name: String,
+    pub context: ContextEntry,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ContextEntry {
+    pub cluster: String,
+    /// Missing for contexts relying on e.g. the cluster's anonymous access
+    #[serde(default)]
+    pub user: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NamedCluster {
+    pub name: String,
+    pub cluster: ClusterEntry,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClusterEntry {
+    pub server: String,
+}
+
+/// Resolved information about the context kubectl is going to use
+#[derive(Serialize, Debug, Clone)]
+pub struct ContextInfo {
+    pub name: String,
+    pub cluster: String,
+    pub server: Option<String>,
+    pub user: String,
+}
+impl Display for ContextInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (cluster: {}, server: {}, user: {})",
+            self.name,
+            self.cluster,
+            self.server.as_deref().unwrap_or("<unknown>"),
+            self.user
+        )
+    }
+}
+
+/// Output of `kubectl version --client`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KubectlVersion {
+    pub client_version: VersionInfo,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub git_version: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(json: &str) -> Service {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn namespaces_are_equal_by_name() {
+        assert_eq!(Namespace::named("web"), Namespace::named("web"));
+        assert_ne!(Namespace::named("web"), Namespace::named("db"));
+    }
+
+    #[test]
+    fn services_are_equal_by_name_and_namespace() {
+        let api = service(r#"{"metadata": {"name": "api", "namespace": "web"}, "spec": {}}"#);
+        let changed = service(
+            r#"{"metadata": {"name": "api", "namespace": "web"}, "spec": {"ports": [{"port": 80}]}}"#,
+        );
+        let other = service(r#"{"metadata": {"name": "api", "namespace": "prod"}, "spec": {}}"#);
+        assert_eq!(api, changed);
+        assert_ne!(api, other);
+    }
+
+    #[test]
+    fn port_with_app_protocol() {
+        let port = serde_json::from_str::<Port>(
+            r#"{"name": "grpc", "port": 9000, "protocol": "TCP", "appProtocol": "grpc"}"#,
+        )
+        .unwrap();
+        assert_eq!(port.app_protocol.as_deref(), Some("grpc"));
+        let json = serde_json::to_value(&port).unwrap();
+        assert_eq!(json["appProtocol"], "grpc");
+    }
+
+    #[test]
+    fn port_without_app_protocol() {
+        let port = serde_json::from_str::<Port>(r#"{"port": 80}"#).unwrap();
+        assert_eq!(port.app_protocol, None);
+        let json = serde_json::to_value(&port).unwrap();
+        assert!(json.get("appProtocol").is_none());
+    }
+
+    #[test]
+    fn metadata_namespace_round_trips() {
+        let api = service(r#"{"metadata": {"name": "api", "namespace": "web"}, "spec": {}}"#);
+        assert_eq!(api.key(), "web/api");
+        assert_eq!(api.namespace(), Some(Namespace::named("web")));
+        let json = serde_json::to_value(&api).unwrap();
+        assert_eq!(json["metadata"]["namespace"], "web");
+        assert_eq!(service(&json.to_string()), api);
+    }
+
+    #[test]
+    fn metadata_without_namespace() {
+        let api = service(r#"{"metadata": {"name": "api"}, "spec": {}}"#);
+        assert_eq!(api.key(), "api");
+        assert_eq!(api.namespace(), None);
+        let json = serde_json::to_value(&api).unwrap();
+        assert!(json["metadata"].get("namespace").is_none());
+    }
+
+    #[test]
+    fn service_without_ports() {
+        let external = service(
+            r#"{"metadata": {"name": "mail"}, "spec": {"type": "ExternalName", "externalName": "mail.example.com"}}"#,
+        );
+        assert!(external.spec.ports.is_empty());
+        let null = service(r#"{"metadata": {"name": "mail"}, "spec": {"ports": null}}"#);
+        assert!(null.spec.ports.is_empty());
+    }
+
+    #[test]
+    fn null_lists_are_empty() {
+        let endpoints = serde_json::from_str::<Endpoints>(
+            r#"{"metadata": {"name": "api"}, "subsets": [{"addresses": null, "ports": [{"port": 8080}]}]}"#,
+        )
+        .unwrap();
+        assert!(!endpoints.is_ready());
+        let endpoints = serde_json::from_str::<Endpoints>(r#"{"subsets": null}"#).unwrap();
+        assert!(endpoints.ready_pods().is_empty());
+
+        let config = serde_json::from_str::<KubeConfig>(
+            r#"{"kind": "Config", "apiVersion": "v1", "preferences": {}, "clusters": null, "users": null, "contexts": null, "current-context": ""}"#,
+        )
+        .unwrap();
+        assert!(config.contexts.is_empty() && config.clusters.is_empty());
+    }
 }
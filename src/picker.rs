@@ -0,0 +1,218 @@
+use std::io;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use console::{Key, Term};
+use dialoguer::theme::Theme;
+
+/// Whether the label contains the typed query (ignoring case)
+pub fn matches(label: &str, query: &str) -> bool {
+    label.to_lowercase().contains(&query.to_lowercase())
+}
+
+/// Items of the picker with the typed filter and the highlighted item
+struct State<T> {
+    items: Vec<(String, T)>,
+    query: String,
+    cursor: usize,
+    /// Lines drawn by the last render, cleared by the next one
+    drawn: usize,
+    done: bool,
+}
+impl<T> State<T> {
+    /// Indices of the items whose label matches the query
+    fn visible(&self) -> Vec<usize> {
+        (0..self.items.len())
+            .filter(|i| matches(&self.items[*i].0, &self.query))
+            .collect()
+    }
+
+    /// Replaces the items, keeping the highlighted one if it still exists
+    fn replace(&mut self, items: Vec<(String, T)>) {
+        let highlighted = self
+            .visible()
+            .get(self.cursor)
+            .map(|i| self.items[*i].0.to_owned());
+        self.items = items;
+        let visible = self.visible();
+        self.cursor = highlighted
+            .and_then(|label| visible.iter().position(|i| self.items[*i].0 == label))
+            .unwrap_or(self.cursor.min(visible.len().saturating_sub(1)));
+    }
+
+    fn render(&mut self, term: &Term, theme: &dyn Theme, prompt: &str) -> io::Result<()> {
+        term.clear_last_lines(self.drawn)?;
+        let lines = self.lines(theme, prompt, term.size());
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        self.drawn = lines.len();
+        Ok(())
+    }
+
+    /// The prompt and the visible items, cut to the width of the terminal since wrapped lines
+    /// wouldn't be cleared by the next render
+    fn lines(&self, theme: &dyn Theme, prompt: &str, (height, width): (u16, u16)) -> Vec<String> {
+        let visible = self.visible();
+        // Scroll so the highlighted item stays on the screen
+        let rows = visible
+            .len()
+            .min((height as usize).saturating_sub(2).max(3));
+        let offset = (self.cursor + 1).saturating_sub(rows);
+        let fit = |line: String| {
+            // NOTE: Measures display columns (not bytes), skipping the ANSI codes of the theme
+            console::truncate_str(&line, (width as usize).saturating_sub(1), "…").into_owned()
+        };
+
+        let mut line = String::new();
+        let _ = theme.format_fuzzy_select_prompt(&mut line, prompt, &self.query, self.query.len());
+        let mut lines = vec![fit(line)];
+        for (row, i) in visible.iter().enumerate().skip(offset).take(rows) {
+            let mut line = String::new();
+            let _ =
+                theme.format_select_prompt_item(&mut line, &self.items[*i].0, row == self.cursor);
+            lines.push(fit(line));
+        }
+        lines
+    }
+}
+
+/// Lets the user pick one of the (label, item) pairs, replacing them with the result of
+/// `refresh` every interval while the prompt is shown (a failed refresh keeps the items).
+/// Typing filters the labels, like the fuzzy selection but by substring.
+pub fn pick<T: Send>(
+    theme: &(dyn Theme + Sync),
+    prompt: &str,
+    items: Vec<(String, T)>,
+    default: Option<usize>,
+    interval: Duration,
+    refresh: impl Fn() -> Option<Vec<(String, T)>> + Sync,
+) -> io::Result<T> {
+    let term = Term::stderr();
+    let state = Mutex::new(State {
+        items,
+        query: String::new(),
+        cursor: default.unwrap_or(0),
+        drawn: 0,
+        done: false,
+    });
+    let lock = || state.lock().unwrap_or_else(|e| e.into_inner());
+
+    thread::scope(|scope| {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let (refresh, lock, term) = (&refresh, &lock, &term);
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(items) = refresh() else {
+                    continue;
+                };
+                let mut state = lock();
+                if state.done {
+                    break;
+                }
+                state.replace(items);
+                let _ = state.render(term, theme, prompt);
+            }
+        });
+
+        let picked = (|| {
+            term.hide_cursor()?;
+            loop {
+                lock().render(term, theme, prompt)?;
+                // NOTE: Not holding the lock while waiting, so refreshes are drawn meanwhile
+                let key = term.read_key();
+                let mut state = lock();
+                let visible = state.visible();
+                match key {
+                    Ok(Key::Enter) if !visible.is_empty() => {
+                        let picked = visible[state.cursor];
+                        break Ok(state.items.swap_remove(picked));
+                    }
+                    Ok(Key::ArrowUp) if !visible.is_empty() => {
+                        state.cursor = (state.cursor + visible.len() - 1) % visible.len()
+                    }
+                    Ok(Key::ArrowDown) if !visible.is_empty() => {
+                        state.cursor = (state.cursor + 1) % visible.len()
+                    }
+                    Ok(Key::Backspace) => {
+                        state.query.pop();
+                        state.cursor = 0;
+                    }
+                    Ok(Key::Char(c)) if !c.is_control() => {
+                        state.query.push(c);
+                        state.cursor = 0;
+                    }
+                    Ok(_) => {}
+                    Err(e) => break Err(e),
+                }
+            }
+        })();
+
+        // Stop the refreshes before cleaning up, also when picking failed
+        let mut state = lock();
+        state.done = true;
+        drop(stop);
+        term.clear_last_lines(state.drawn)?;
+        term.show_cursor()?;
+        let (label, item) = picked?;
+        let mut line = String::new();
+        let _ = theme.format_select_prompt_selection(&mut line, prompt, &label);
+        term.write_line(&line)?;
+        Ok(item)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dialoguer::theme::SimpleTheme;
+
+    use crate::kubectl::context;
+
+    #[test]
+    fn filters_non_ascii_labels_ignoring_case() {
+        let state = State {
+            items: ["dev", "prød-zürich", "東京"]
+                .map(|l| (l.to_owned(), ()))
+                .to_vec(),
+            query: String::from("ZÜR"),
+            cursor: 0,
+            drawn: 0,
+            done: false,
+        };
+        assert_eq!(state.visible(), [1]);
+    }
+
+    #[test]
+    fn renders_long_non_ascii_labels_within_the_width() {
+        // NOTE: Decomposed "é" (e + combining acute accent) and double-width characters
+        let long = "prød-zu\u{308}rich-e\u{301}ast-東京-cluster-with-a-very-long-name";
+        let contexts = ["dev", long, "staging"];
+        let current = format!("{long} ");
+        let default = contexts.iter().position(|c| context::matches(c, &current));
+        assert_eq!(default, Some(1));
+        let state = State {
+            items: contexts.map(|c| (c.to_owned(), ())).to_vec(),
+            query: String::from("ZU\u{308}R"),
+            cursor: 0,
+            drawn: 0,
+            done: false,
+        };
+        assert_eq!(state.visible(), [1]);
+
+        let lines = state.lines(&SimpleTheme, "Select context", (24, 30));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "Select context ZU\u{308}R|");
+        assert!(
+            lines[1].starts_with("> prød-zu\u{308}rich-e\u{301}ast-東京"),
+            "{}",
+            lines[1]
+        );
+        assert!(lines[1].ends_with('…'));
+        for line in &lines {
+            assert!(console::measure_text_width(line) <= 29, "{line}");
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How long to wait for a replaced forward to release its ports
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running kpfr instance, tracked by a pidfile in the config directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackedForward {
+    pub pid: u32,
+    /// Start time of the process, telling it apart from a later one that got the same pid
+    #[serde(default)]
+    pub started: Option<String>,
+    /// Service key (`namespace/service`)
+    pub service: String,
+    /// Local port for each remote port
+    pub ports: HashMap<u16, u16>,
+}
+impl TrackedForward {
+    /// Checks whether the tracked kpfr still runs (and not just another process with its pid)
+    fn is_running(&self) -> bool {
+        self.started.is_some() && start_time(self.pid) == self.started
+    }
+
+    /// Terminates the tracked kpfr (which stops its kubectl) and waits until it is gone
+    pub fn terminate(&self) -> Result<bool> {
+        if !self.is_running() {
+            return Ok(true);
+        }
+        signal_terminate(self.pid)?;
+        let started = Instant::now();
+        while self.is_running() {
+            if started.elapsed() >= TERMINATE_TIMEOUT {
+                return Ok(false);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(true)
+    }
+}
+
+/// Directory holding one pidfile per running forward
+pub fn directory(config_dir: &Path) -> PathBuf {
+    config_dir.join("forwards")
+}
+
+fn pidfile(config_dir: &Path, pid: u32) -> PathBuf {
+    directory(config_dir).join(format!("{pid}.json"))
+}
+
+/// Tracks the current process as forwarding the given ports
+pub fn register(config_dir: &Path, service: &str, ports: &HashMap<u16, u16>) -> Result<()> {
+    fs::create_dir_all(directory(config_dir))?;
+    let forward = TrackedForward {
+        pid: process::id(),
+        started: start_time(process::id()),
+        service: service.to_owned(),
+        ports: ports.to_owned(),
+    };
+    let data = serde_json::to_string_pretty(&forward)?;
+    File::create(pidfile(config_dir, forward.pid))?.write_all(data.as_bytes())
+}
+
+/// Stops tracking the current process
+pub fn unregister(config_dir: &Path) {
+    let _ = fs::remove_file(pidfile(config_dir, process::id()));
+}
+
+/// All forwards whose process is still running, removing stale pidfiles on the way
+pub fn list(config_dir: &Path) -> Vec<TrackedForward> {
+    let Ok(entries) = fs::read_dir(directory(config_dir)) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let forward = File::open(&path)
+                .ok()
+                .and_then(|f| serde_json::from_reader::<_, TrackedForward>(BufReader::new(f)).ok());
+            match forward {
+                Some(forward) if forward.pid == process::id() => None,
+                Some(forward) if forward.is_running() => Some(forward),
+                _ => {
+                    let _ = fs::remove_file(&path);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The tracked forward listening on the given local port
+pub fn owner_of(config_dir: &Path, local_port: u16) -> Option<TrackedForward> {
+    list(config_dir)
+        .into_iter()
+        .find(|forward| forward.ports.values().any(|port| *port == local_port))
+}
+
+/// Start time of the process with the pid, `None` if there is none.
+/// On Linux the clock ticks since boot, elsewhere as reported by `ps`.
+#[cfg(target_os = "linux")]
+fn start_time(pid: u32) -> Option<String> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // NOTE: The command name may contain spaces and parentheses, the fields after it don't
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19).map(str::to_owned)
+}
+#[cfg(all(unix, not(target_os = "linux")))]
+fn start_time(pid: u32) -> Option<String> {
+    let output = process::Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let started = String::from_utf8(output.stdout).ok()?;
+    Some(started.trim().to_owned()).filter(|s| output.status.success() && !s.is_empty())
+}
+#[cfg(not(unix))]
+fn start_time(_pid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn signal_terminate(pid: u32) -> Result<()> {
+    let pid = libc::pid_t::try_from(pid).map_err(std::io::Error::other)?;
+    // SAFETY: kill only sends a signal, the pid was checked to still be the tracked kpfr
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == 0 {
+        return Ok(());
+    }
+    // Exiting meanwhile is just as good
+    match std::io::Error::last_os_error() {
+        e if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+        e => Err(e),
+    }
+}
+#[cfg(not(unix))]
+fn signal_terminate(_pid: u32) -> Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn tracked(started: Option<String>) -> TrackedForward {
+        TrackedForward {
+            pid: process::id(),
+            started,
+            service: "web/api".to_owned(),
+            ports: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn running_with_the_same_start_time() {
+        assert!(tracked(start_time(process::id())).is_running());
+    }
+
+    #[test]
+    fn reused_pid_is_not_running() {
+        assert!(!tracked(Some("0".to_owned())).is_running());
+        assert!(!tracked(None).is_running());
+    }
+}
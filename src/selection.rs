@@ -1,18 +1,88 @@
 #![allow(unused)]
 
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, Result, Write};
-use std::path::Path;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use crate::cli::{ConfigFormat, ContextSort};
+use crate::error::MainError;
 use crate::model::{Namespace, Service};
+use crate::toml;
+
+/// Port mappings (`{service: {remote: local}}`) of a ports file
+pub type PortMappings = HashMap<String, HashMap<u16, u16>>;
+
+/// Reads port mappings to forward from a JSON file
+pub fn read_port_mappings<P: AsRef<Path>>(
+    filename: &P,
+) -> std::result::Result<PortMappings, MainError> {
+    let path = filename.as_ref().to_path_buf();
+    let file = File::open(&path).map_err(|e| MainError::UnreadablePortsFile(path.clone(), e))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| MainError::InvalidPortsFile(path, e))
+}
+
+/// User preferences stored in the config, written back unchanged on save
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    /// Friendly service names, either global (`db`) or namespaced (`web/db`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub aliases: HashMap<String, String>,
+    /// Local address to listen on per service (`namespace/service` or `service`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub addresses: HashMap<String, String>,
+    /// Glob patterns of contexts which require typing the context name before forwarding
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected_contexts: Vec<String>,
+    /// Use fuzzy matching in the selection prompts (default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuzzy: Option<bool>,
+    /// Always use the current context instead of prompting for one (default: false)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_context: Option<bool>,
+    /// File to append a JSON line to for every finished forward (off by default)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_log: Option<String>,
+    /// Show the number of items in the selection prompts (default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_counts: Option<bool>,
+    /// Order of the context selection (default: alphabetical)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_sort: Option<ContextSort>,
+    /// Forward the only port of a service without showing the port selection (default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_select_single_port: Option<bool>,
+    /// Glob patterns of services (`namespace/service`) to confirm when picked in the selection
+    /// (default: the `kubernetes` API and `kube-dns` services, `[]` disables it)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm_services: Option<Vec<String>>,
+}
+
+/// How many contexts are remembered for `--context-sort recent`
+const MAX_RECENT_CONTEXTS: usize = 20;
 
 #[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Selection {
     pub namespace: String,
     pub ports: HashMap<String, HashMap<u16, u16>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub service_ports: HashMap<String, Vec<u16>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace_selector: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recent_contexts: Vec<String>,
+    /// Settings as written in the config file, before expanding environment variables
+    #[serde(flatten)]
+    pub raw_settings: Settings,
+    #[serde(skip)]
+    pub settings: Settings,
 }
 impl Selection {
     pub fn from_defaults(namespace: &Namespace, defaults: &Option<DefaultSelections>) -> Self {
@@ -22,20 +92,50 @@ impl Selection {
                 .as_ref()
                 .and_then(|d| d.ports.clone())
                 .unwrap_or_default(),
+            service_ports: defaults
+                .as_ref()
+                .and_then(|d| d.service_ports.clone())
+                .unwrap_or_default(),
+            namespace_selector: defaults.as_ref().and_then(|d| d.namespace_selector.clone()),
+            address: defaults.as_ref().and_then(|d| d.address.clone()),
+            recent_contexts: defaults
+                .as_ref()
+                .and_then(|d| d.recent_contexts.clone())
+                .unwrap_or_default(),
+            raw_settings: defaults
+                .as_ref()
+                .map(|d| d.raw_settings.clone())
+                .unwrap_or_default(),
+            settings: defaults
+                .as_ref()
+                .map(|d| d.settings.clone())
+                .unwrap_or_default(),
         }
     }
 
+    /// Moves the context to the front of the recently used ones
+    pub fn record_context(&mut self, context: &str) {
+        self.recent_contexts.retain(|c| c != context);
+        self.recent_contexts.insert(0, context.to_owned());
+        self.recent_contexts.truncate(MAX_RECENT_CONTEXTS);
+    }
+
     pub fn set_last_service(self, service: &Service) -> SelectionWithService {
         SelectionWithService {
-            last_service: service.metadata.name.to_owned(),
+            last_service: service.key(),
             namespace: self.namespace,
             ports: self.ports,
+            service_ports: self.service_ports,
+            namespace_selector: self.namespace_selector,
+            address: self.address,
+            recent_contexts: self.recent_contexts,
+            raw_settings: self.raw_settings,
+            settings: self.settings,
         }
     }
 
     pub fn save<P: AsRef<Path>>(&self, filename: &P) -> Result<()> {
-        let data = serde_json::to_string_pretty(self).unwrap();
-        File::create(filename).unwrap().write_all(data.as_bytes())
+        write_config(filename, &serde_json::to_value(self)?)
     }
 }
 
@@ -44,23 +144,83 @@ impl Selection {
 pub struct SelectionWithService {
     pub namespace: String,
     pub ports: HashMap<String, HashMap<u16, u16>>,
+    /// Ports each service exposed when it was last forwarded, to detect changes
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub service_ports: HashMap<String, Vec<u16>>,
     pub last_service: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace_selector: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Contexts forwarded in, most recent first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recent_contexts: Vec<String>,
+    /// Settings as written in the config file, before expanding environment variables
+    #[serde(flatten)]
+    pub raw_settings: Settings,
+    #[serde(skip)]
+    pub settings: Settings,
 }
 impl SelectionWithService {
     pub fn save<P: AsRef<Path>>(&self, filename: &P) -> Result<()> {
-        let data = serde_json::to_string_pretty(self).unwrap();
-        File::create(filename).unwrap().write_all(data.as_bytes())
+        write_config(filename, &serde_json::to_value(self)?)
     }
 
     pub fn set_last_service(self, service: &Service) -> Self {
         Self {
-            last_service: service.metadata.name.to_owned(),
+            last_service: service.key(),
             ..self
         }
     }
 
+    /// Local address for the service: per-service override, then the flag,
+    /// then the last used address (unless it listened on all interfaces)
+    pub fn resolve_address(
+        &self,
+        service: &Service,
+        requested: Option<IpAddr>,
+    ) -> std::result::Result<Option<IpAddr>, MainError> {
+        let addresses = &self.settings.addresses;
+        let configured = addresses
+            .get(&service.key())
+            .or_else(|| addresses.get(&service.metadata.name));
+        if let Some(address) = configured {
+            return parse_address(address).map(Some);
+        }
+        if requested.is_some() {
+            return Ok(requested);
+        }
+        let remembered = self.address.as_deref().map(parse_address).transpose()?;
+        Ok(remembered.filter(|address| !address.is_unspecified()))
+    }
+
+    /// Remembers the ports the service currently exposes, returning the previously
+    /// remembered ones if they differ (`Some(vec![])` if none were remembered)
+    pub fn record_service_ports(&mut self, service: &Service) -> Option<Vec<u16>> {
+        let mut ports = service
+            .spec
+            .ports
+            .iter()
+            .map(|p| p.port)
+            .collect::<Vec<_>>();
+        ports.sort();
+        ports.dedup();
+        let previous = self.service_ports.insert(service.key(), ports.clone());
+        match previous {
+            Some(previous) if previous == ports => None,
+            previous => Some(previous.unwrap_or_default()),
+        }
+    }
+
     pub fn ports_for(&mut self, service: &Service) -> &mut HashMap<u16, u16> {
-        self.ports.entry(service.metadata.name.clone()).or_default()
+        // Migrate mappings saved before services were keyed by namespace
+        let key = service.key();
+        if !self.ports.contains_key(&key)
+            && let Some(legacy) = self.ports.remove(&service.metadata.name)
+        {
+            self.ports.insert(key.clone(), legacy);
+        }
+        self.ports.entry(key).or_default()
     }
 }
 
@@ -70,11 +230,361 @@ pub struct DefaultSelections {
     pub namespace: Option<String>,
     pub last_service: Option<String>,
     pub ports: Option<HashMap<String, HashMap<u16, u16>>>,
+    /// Ports each service exposed when it was last forwarded
+    pub service_ports: Option<HashMap<String, Vec<u16>>>,
+    /// Label selector used for the last namespace listing
+    pub namespace_selector: Option<String>,
+    /// Last used local address to listen on
+    pub address: Option<String>,
+    /// Contexts forwarded in, most recent first
+    pub recent_contexts: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Settings as written in the file, before expanding environment variables
+    #[serde(skip)]
+    pub raw_settings: Settings,
 }
 impl DefaultSelections {
-    pub fn read<P: AsRef<Path>>(filename: &P) -> Option<Self> {
-        let file = File::open(filename).ok()?;
-        let reader = BufReader::new(file);
-        serde_json::from_reader(reader).ok()
+    /// Reads the config, `None` if there is none yet
+    pub fn read<P: AsRef<Path>>(filename: &P) -> std::result::Result<Option<Self>, MainError> {
+        let path = filename.as_ref();
+        let invalid = |e| MainError::InvalidConfig(path.to_path_buf(), e);
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(invalid(serde_json::Error::io(e))),
+        };
+        let mut value = parse_config(path, &data).map_err(invalid)?;
+
+        // Keep the unexpanded settings, so saving doesn't bake in the environment
+        let raw_settings = serde_json::from_value::<Settings>(value.clone()).map_err(invalid)?;
+        expand_env_values(&mut value);
+        let mut defaults = serde_json::from_value::<Self>(value).map_err(invalid)?;
+        defaults.raw_settings = raw_settings;
+        Ok(Some(defaults))
+    }
+
+    /// Checks whether the file parses as a config, e.g. after editing it by hand
+    pub fn check<P: AsRef<Path>>(filename: &P) -> std::result::Result<(), MainError> {
+        Self::read(filename).map(|_| ())
+    }
+
+    /// Names of all services with remembered settings, without contacting the cluster
+    pub fn known_services(&self) -> Vec<String> {
+        let mut services = self
+            .ports
+            .iter()
+            .flat_map(|p| p.keys())
+            .chain(&self.last_service)
+            .map(|key| key.rsplit('/').next().unwrap_or(key).to_owned())
+            .collect::<Vec<_>>();
+        services.sort();
+        services.dedup();
+        services
+    }
+
+    /// Resolves a service alias, preferring `<namespace>/<alias>` over global aliases
+    pub fn resolve_alias(&self, namespace: Option<&str>, service: &str) -> String {
+        let aliases = &self.settings.aliases;
+        namespace
+            .and_then(|ns| aliases.get(&format!("{ns}/{service}")))
+            .or_else(|| aliases.get(service))
+            .cloned()
+            .unwrap_or_else(|| service.to_owned())
+    }
+}
+
+/// Removes the remembered ports of the service (`service` or `namespace/service`), or of all
+/// services, keeping everything else of the config. Returns the forgotten service keys.
+pub fn forget_ports<P: AsRef<Path>>(filename: &P, service: Option<&str>) -> Result<Vec<String>> {
+    let data = fs::read_to_string(filename)?;
+    let mut value = parse_config(filename.as_ref(), &data)?;
+    let matches = |key: &str| match service {
+        Some(service) if service.contains('/') => key == service,
+        Some(service) => key.rsplit('/').next() == Some(service),
+        None => true,
+    };
+
+    let mut forgotten = Vec::new();
+    for field in ["ports", "servicePorts"] {
+        if let Some(map) = value.get_mut(field).and_then(|v| v.as_object_mut()) {
+            let keys = map
+                .keys()
+                .filter(|k| matches(k))
+                .cloned()
+                .collect::<Vec<_>>();
+            for key in keys {
+                map.remove(&key);
+                if !forgotten.contains(&key) {
+                    forgotten.push(key);
+                }
+            }
+        }
+    }
+    if let Some(object) = value.as_object_mut()
+        && object
+            .get("lastService")
+            .and_then(|v| v.as_str())
+            .is_some_and(|last| service.is_some() && matches(last))
+    {
+        object.remove("lastService");
+    }
+    forgotten.sort();
+
+    write_config(filename, &value)?;
+    Ok(forgotten)
+}
+
+/// The config file in the directory: the one of the given format, otherwise `config.toml` if
+/// it's the only one present, otherwise `config.json`
+pub fn config_file(config_dir: &Path, format: Option<ConfigFormat>) -> PathBuf {
+    let json = config_dir.join("config.json");
+    let toml = config_dir.join("config.toml");
+    match format {
+        Some(ConfigFormat::Json) => json,
+        Some(ConfigFormat::Toml) => toml,
+        None if toml.exists() && !json.exists() => toml,
+        None => json,
+    }
+}
+
+/// Parses the config in the format of the file's extension
+fn parse_config(path: &Path, data: &str) -> serde_json::Result<serde_json::Value> {
+    match ConfigFormat::of(path) {
+        ConfigFormat::Json => serde_json::from_str(data),
+        ConfigFormat::Toml => toml::from_str(data).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Writes the config in the format of the file's extension
+fn write_config<P: AsRef<Path>>(filename: &P, value: &serde_json::Value) -> Result<()> {
+    let data = match ConfigFormat::of(filename.as_ref()) {
+        ConfigFormat::Json => serde_json::to_string_pretty(value)?,
+        ConfigFormat::Toml => toml::to_string(value),
+    };
+    create_parent_dir(filename)?;
+    File::create(filename)?.write_all(data.as_bytes())
+}
+
+/// Creates the config directory on the first save, so read-only runs leave no traces
+fn create_parent_dir<P: AsRef<Path>>(filename: &P) -> Result<()> {
+    match filename.as_ref().parent() {
+        // NOTE: An existing (possibly read-only mounted) directory is left alone
+        Some(dir) if !dir.is_dir() => fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+}
+
+fn parse_address(address: &str) -> std::result::Result<IpAddr, MainError> {
+    address
+        .parse()
+        .map_err(|_| MainError::InvalidAddress(address.to_owned()))
+}
+
+/// Expands environment variables in all string values (not keys) of the JSON
+fn expand_env_values(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) => *s = expand_env(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(expand_env_values),
+        serde_json::Value::Object(map) => map.values_mut().for_each(expand_env_values),
+        _ => {}
+    }
+}
+
+/// Expands `$VAR` and `${VAR}` references, keeping unset variables as they are
+pub fn expand_env(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, reference_len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        let reference = &rest[start..start + 1 + reference_len];
+        if name.is_empty() {
+            output.push_str(reference);
+        } else if let Ok(value) = std::env::var(name) {
+            output.push_str(&value);
+        } else {
+            eprintln!("Warning: environment variable '{name}' used in the config is not set");
+            output.push_str(reference);
+        }
+        rest = &rest[start + 1 + reference_len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the config to a fresh temporary file
+    fn config_file(name: &str, data: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kpfr-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join(name);
+        fs::write(&filename, data).unwrap();
+        filename
+    }
+
+    fn service(namespace: &str, name: &str) -> Service {
+        serde_json::from_value(serde_json::json!({
+            "metadata": {"name": name, "namespace": namespace},
+            "spec": {"ports": [{"port": 80}]},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn same_named_services_do_not_collide() {
+        let filename = config_file("same-named.json", "{}");
+        let defaults = DefaultSelections::read(&filename).unwrap();
+        let mut selection = Selection::from_defaults(&Namespace::named("web"), &defaults)
+            .set_last_service(&service("web", "api"));
+        selection.ports_for(&service("web", "api")).insert(80, 8080);
+        selection
+            .ports_for(&service("prod", "api"))
+            .insert(80, 9080);
+        selection.save(&filename).unwrap();
+
+        let ports = DefaultSelections::read(&filename)
+            .unwrap()
+            .and_then(|d| d.ports)
+            .unwrap();
+        assert_eq!(ports["web/api"], HashMap::from([(80, 8080)]));
+        assert_eq!(ports["prod/api"], HashMap::from([(80, 9080)]));
+    }
+
+    #[test]
+    fn legacy_ports_move_to_the_namespaced_key() {
+        let filename = config_file("legacy.json", r#"{"ports": {"api": {"80": 8080}}}"#);
+        let defaults = DefaultSelections::read(&filename).unwrap();
+        let mut selection = Selection::from_defaults(&Namespace::named("web"), &defaults)
+            .set_last_service(&service("web", "api"));
+        assert_eq!(
+            selection.ports_for(&service("web", "api")),
+            &HashMap::from([(80, 8080)])
+        );
+        assert!(!selection.ports.contains_key("api"));
+    }
+
+    const FORGET_CONFIG: &str = r#"{
+        "namespace": "web",
+        "lastService": "web/api",
+        "ports": {"web/api": {"80": 8080}, "prod/api": {"80": 9080}, "web/db": {"5432": 5432}},
+        "servicePorts": {"web/api": [80]},
+        "aliases": {"db": "web/db"}
+    }"#;
+
+    #[test]
+    fn forgets_the_ports_of_one_service() {
+        let filename = config_file("forget-one.json", FORGET_CONFIG);
+        let forgotten = forget_ports(&filename, Some("web/api")).unwrap();
+        assert_eq!(forgotten, ["web/api"]);
+
+        let defaults = DefaultSelections::read(&filename).unwrap().unwrap();
+        let ports = defaults.ports.unwrap();
+        assert!(!ports.contains_key("web/api"));
+        assert!(ports.contains_key("prod/api"));
+        assert!(defaults.service_ports.unwrap().is_empty());
+        assert_eq!(defaults.last_service, None);
+        assert_eq!(defaults.settings.aliases["db"], "web/db");
+    }
+
+    #[test]
+    fn forgets_a_service_name_in_every_namespace() {
+        let filename = config_file("forget-name.json", FORGET_CONFIG);
+        let forgotten = forget_ports(&filename, Some("api")).unwrap();
+        assert_eq!(forgotten, ["prod/api", "web/api"]);
+    }
+
+    #[test]
+    fn forgets_all_ports_keeping_the_rest() {
+        let filename = config_file("forget-all.json", FORGET_CONFIG);
+        let forgotten = forget_ports(&filename, None).unwrap();
+        assert_eq!(forgotten, ["prod/api", "web/api", "web/db"]);
+
+        let defaults = DefaultSelections::read(&filename).unwrap().unwrap();
+        assert!(defaults.ports.unwrap().is_empty());
+        assert_eq!(defaults.namespace.as_deref(), Some("web"));
+        assert_eq!(defaults.last_service.as_deref(), Some("web/api"));
+    }
+
+    #[test]
+    fn missing_config_is_none() {
+        let filename = std::env::temp_dir().join("kpfr-test-missing/config.json");
+        assert!(DefaultSelections::read(&filename).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_config_is_an_error() {
+        let filename = config_file(
+            "invalid.json",
+            r#"{"namespace": "web", "protectedContexts": ["prod-*"],}"#,
+        );
+        assert!(matches!(
+            DefaultSelections::read(&filename),
+            Err(MainError::InvalidConfig(_, _))
+        ));
+    }
+
+    #[test]
+    fn saves_and_reads_a_toml_config() {
+        let filename = config_file(
+            "config.toml",
+            "# Hand-written\nnamespace = \"web\"\nprotectedContexts = [\"prod-*\"]\n",
+        );
+        let defaults = DefaultSelections::read(&filename).unwrap();
+        let mut selection = Selection::from_defaults(&Namespace::named("web"), &defaults)
+            .set_last_service(&service("web", "api"));
+        selection.ports_for(&service("web", "api")).insert(80, 8080);
+        selection.save(&filename).unwrap();
+
+        let data = fs::read_to_string(&filename).unwrap();
+        assert!(data.contains("[ports.\"web/api\"]\n80 = 8080\n"), "{data}");
+        let defaults = DefaultSelections::read(&filename).unwrap().unwrap();
+        assert_eq!(
+            defaults.ports.unwrap()["web/api"],
+            HashMap::from([(80, 8080)])
+        );
+        assert_eq!(defaults.settings.protected_contexts, ["prod-*"]);
+
+        assert_eq!(forget_ports(&filename, Some("api")).unwrap(), ["web/api"]);
+        assert!(toml::from_str(&fs::read_to_string(&filename).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn invalid_toml_config_is_an_error() {
+        let filename = config_file("invalid.toml", "namespace = web\n");
+        assert!(matches!(
+            DefaultSelections::read(&filename),
+            Err(MainError::InvalidConfig(_, _))
+        ));
+    }
+
+    #[test]
+    fn prefers_the_only_present_config() {
+        let dir = std::env::temp_dir().join(format!("kpfr-test-{}-formats", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(super::config_file(&dir, None), dir.join("config.json"));
+        fs::write(dir.join("config.toml"), "").unwrap();
+        assert_eq!(super::config_file(&dir, None), dir.join("config.toml"));
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        assert_eq!(super::config_file(&dir, None), dir.join("config.json"));
+        assert_eq!(
+            super::config_file(&dir, Some(ConfigFormat::Toml)),
+            dir.join("config.toml")
+        );
     }
 }
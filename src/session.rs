@@ -0,0 +1,55 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Result, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::forward::ForwardHandle;
+use crate::kubectl::ForwardSpec;
+
+/// Summary of a finished forward, written as one JSON line per session
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRecord {
+    pub context: Option<String>,
+    pub namespace: String,
+    pub service: String,
+    /// Unix timestamps in seconds
+    pub start: u64,
+    pub end: u64,
+    pub reconnects: u32,
+}
+impl SessionRecord {
+    pub fn new(context: Option<String>, spec: &ForwardSpec, forward: &ForwardHandle) -> Self {
+        let end = SystemTime::now();
+        let start = end - forward.started.elapsed();
+        Self {
+            context,
+            namespace: spec.namespace.metadata.name.to_owned(),
+            service: spec.service.metadata.name.to_owned(),
+            start: unix_seconds(start),
+            end: unix_seconds(end),
+            reconnects: forward.reconnects,
+        }
+    }
+
+    /// Appends the record to the session log
+    pub fn append<P: AsRef<Path>>(&self, filename: &P) -> Result<()> {
+        if let Some(dir) = filename.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let line = serde_json::to_string(self)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(filename)?;
+        writeln!(file, "{line}")
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
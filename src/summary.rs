@@ -0,0 +1,341 @@
+use crate::kubectl::ForwardSpec;
+
+use crate::model::{Endpoints, Port, Service};
+use crate::yaml;
+
+/// Remote ports which usually serve HTTP, used when the port has no telling name
+const HTTP_PORTS: [u16; 6] = [80, 3000, 5000, 8000, 8080, 8888];
+const HTTPS_PORTS: [u16; 2] = [443, 8443];
+
+/// Declared application protocol, without the `kubernetes.io/` prefix of the standard ones
+fn app_protocol(port: &Port) -> Option<String> {
+    let protocol = port.app_protocol.as_deref()?.to_lowercase();
+    Some(
+        protocol
+            .strip_prefix("kubernetes.io/")
+            .map(String::from)
+            .unwrap_or(protocol),
+    )
+}
+
+/// Whether the port serves gRPC, which can't be opened in a browser
+pub fn is_grpc(port: &Port) -> bool {
+    match app_protocol(port) {
+        Some(protocol) => protocol == "grpc",
+        None => {
+            let name = port.name.as_deref().unwrap_or_default().to_lowercase();
+            name.starts_with("grpc")
+        }
+    }
+}
+
+/// URL scheme of a service port, from its declared application protocol or
+/// guessed from its name and number
+pub fn url_scheme(port: &Port) -> Option<&'static str> {
+    if let Some(protocol) = app_protocol(port) {
+        return match protocol.as_str() {
+            "https" | "wss" => Some("https"),
+            "http" | "h2c" | "ws" => Some("http"),
+            _ => None,
+        };
+    }
+    let name = port.name.as_deref().unwrap_or_default().to_lowercase();
+    if is_grpc(port) {
+        None
+    } else if name.starts_with("https") || HTTPS_PORTS.contains(&port.port) {
+        Some("https")
+    } else if name.starts_with("http") || name == "web" || HTTP_PORTS.contains(&port.port) {
+        Some("http")
+    } else {
+        None
+    }
+}
+
+/// Wraps the text into an OSC 8 hyperlink escape sequence
+fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\")
+}
+
+/// Host to reach the forwarded ports on
+pub fn host(spec: &ForwardSpec) -> String {
+    match spec.address {
+        Some(address) if !address.is_unspecified() && !address.is_loopback() => address.to_string(),
+        _ => String::from("localhost"),
+    }
+}
+
+/// Prints the mappings of a running forward, rendering HTTP ports as (clickable) URLs
+pub fn print(spec: &ForwardSpec, hyperlinks: bool) {
+    let service = &spec.service;
+    let mut mappings = spec.ports.iter().collect::<Vec<_>>();
+    mappings.sort();
+    let host = host(spec);
+
+    match spec.address {
+        Some(address) if address.is_unspecified() => {
+            eprintln!(
+                "Forwarding {} on all interfaces ({address}):",
+                spec.target()
+            )
+        }
+        _ => eprintln!("Forwarding {}:", spec.target()),
+    }
+    for (remote, local) in mappings {
+        let port = service.spec.ports.iter().find(|p| p.port == *remote);
+        let target = match port.and_then(url_scheme) {
+            Some(scheme) => {
+                let url = format!("{scheme}://{host}:{local}");
+                if hyperlinks {
+                    hyperlink(&url, &url)
+                } else {
+                    url
+                }
+            }
+            None if port.is_some_and(is_grpc) => {
+                format!("{host}:{local} (gRPC, not browsable)")
+            }
+            None => match port.and_then(app_protocol) {
+                Some(protocol) => format!("{host}:{local} ({protocol})"),
+                None => format!("{host}:{local}"),
+            },
+        };
+        eprintln!("  {} → {target}", spec.remote_port(*remote));
+    }
+}
+
+/// Prints a compact description of the service, with its ready pods if they could be looked up
+pub fn print_description(service: &Service, endpoints: Option<&Endpoints>) {
+    let spec = &service.spec;
+    eprintln!("Service {}:", service.key());
+    eprintln!(
+        "  Type:      {} (cluster IP: {})",
+        spec.service_type(),
+        spec.cluster_ip.as_deref().unwrap_or("-")
+    );
+    let selector = spec
+        .selector
+        .iter()
+        .flatten()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>();
+    eprintln!(
+        "  Selector:  {}",
+        if selector.is_empty() {
+            String::from("-")
+        } else {
+            selector.join(",")
+        }
+    );
+    let mut rows = Vec::new();
+    for port in &spec.ports {
+        rows.push(vec![
+            port.port.to_string(),
+            port.name.to_owned().unwrap_or_else(|| String::from("-")),
+            port.protocol().to_owned(),
+            port.target_port
+                .as_ref()
+                .map(|t| format!("→ {t}"))
+                .unwrap_or_default(),
+        ]);
+    }
+    eprintln!("  Ports:");
+    for line in table(&rows).lines() {
+        eprintln!("    {line}");
+    }
+    if let Some(endpoints) = endpoints {
+        let pods = endpoints.ready_pods();
+        match pods.len() {
+            0 => eprintln!("  Endpoints: none ready"),
+            n => eprintln!("  Endpoints: {n} ready ({})", pods.join(", ")),
+        }
+    }
+}
+
+/// Prints the `/etc/hosts` line which would make the forward reachable as `<service>.local`
+pub fn print_hosts_suggestion(spec: &ForwardSpec) {
+    let address = match spec.address {
+        Some(address) if !address.is_unspecified() => address.to_string(),
+        _ => String::from("127.0.0.1"),
+    };
+    let hostname = format!("{}.local", spec.service.metadata.name);
+    let mut ports = spec.ports.values().collect::<Vec<_>>();
+    ports.sort();
+    let endpoints = ports
+        .iter()
+        .map(|port| format!("{hostname}:{port}"))
+        .collect::<Vec<_>>();
+    eprintln!(
+        "To reach it as {}, add to /etc/hosts:",
+        endpoints.join(", ")
+    );
+    eprintln!("  {address} {hostname}");
+}
+
+/// Pads the cells of every row to align the columns
+pub fn table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    rows.iter()
+        .map(|row| {
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>();
+            cells.join("  ").trim_end().to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints the mappings of a running forward as aligned columns
+pub fn print_table(spec: &ForwardSpec) {
+    eprintln!("{}", table(&table_rows(spec)));
+}
+
+/// Header and one row per mapping of the forward, sorted by remote port
+fn table_rows(spec: &ForwardSpec) -> Vec<Vec<String>> {
+    let service = &spec.service;
+    let mut mappings = spec.ports.iter().collect::<Vec<_>>();
+    mappings.sort();
+    // NOTE: Unlike in URLs, show where kubectl really listens
+    let host = match spec.address {
+        Some(address) if address.is_unspecified() => address.to_string(),
+        _ => host(spec),
+    };
+
+    let mut rows = vec![
+        ["SERVICE", "PORT", "REMOTE", "LOCAL", "PROTOCOL"]
+            .map(String::from)
+            .to_vec(),
+    ];
+    for (remote, local) in mappings {
+        let port = service.spec.ports.iter().find(|p| p.port == *remote);
+        rows.push(vec![
+            service.key(),
+            port.and_then(|p| p.name.to_owned())
+                .unwrap_or_else(|| String::from("-")),
+            spec.remote_port(*remote).to_string(),
+            format!("{host}:{local}"),
+            protocol(port),
+        ]);
+    }
+    rows
+}
+
+/// Prints the forward as a YAML document, e.g. for `-o yaml`
+pub fn print_yaml(spec: &ForwardSpec) {
+    eprint!("{}", yaml::to_string(&forward_value(spec)));
+}
+
+/// The forward with one entry per mapping, sorted by remote port
+fn forward_value(spec: &ForwardSpec) -> serde_json::Value {
+    let mut mappings = spec.ports.iter().collect::<Vec<_>>();
+    mappings.sort();
+    let ports = mappings
+        .into_iter()
+        .map(|(remote, local)| {
+            let port = spec.service.spec.ports.iter().find(|p| p.port == *remote);
+            // NOTE: Named target ports of a pod stay names
+            let target = spec.remote_port(*remote).to_string();
+            serde_json::json!({
+                "name": port.and_then(|p| p.name.as_deref()),
+                "remote": target.parse::<u16>().map_or_else(|_| target.into(), serde_json::Value::from),
+                "local": local,
+                "protocol": protocol(port),
+            })
+        })
+        .collect::<Vec<_>>();
+    let address = match spec.address {
+        Some(address) if address.is_unspecified() => address.to_string(),
+        _ => host(spec),
+    };
+    serde_json::json!({
+        "service": spec.service.key(),
+        "target": spec.target(),
+        "address": address,
+        "ports": ports,
+    })
+}
+
+fn protocol(port: Option<&Port>) -> String {
+    match port {
+        Some(port) if is_grpc(port) => String::from("grpc"),
+        Some(port) => url_scheme(port)
+            .map(String::from)
+            .or_else(|| app_protocol(port))
+            .unwrap_or_else(|| String::from("tcp")),
+        None => String::from("tcp"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::model::Namespace;
+
+    fn spec(ports: &[(u16, u16)]) -> ForwardSpec {
+        let service = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "api", "namespace": "web"},
+            "spec": {"ports": [
+                {"name": "http", "port": 80, "targetPort": 8080},
+                {"name": "grpc", "port": 9000, "appProtocol": "grpc"},
+                {"port": 5432},
+            ]},
+        }))
+        .unwrap();
+        ForwardSpec {
+            namespace: Namespace::named("web"),
+            service,
+            ports: HashMap::from_iter(ports.iter().copied()),
+            address: None,
+            pod: None,
+        }
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        let rows = [
+            vec!["SERVICE", "PORT"],
+            vec!["web/api", "http"],
+            vec!["db", ""],
+        ]
+        .map(|row| row.into_iter().map(String::from).collect::<Vec<_>>());
+        assert_eq!(table(&rows), "SERVICE  PORT\nweb/api  http\ndb");
+    }
+
+    #[test]
+    fn table_rows_per_mapping() {
+        let rows = table_rows(&spec(&[(9000, 19000), (80, 18080), (5432, 15432)]));
+        let expected = [
+            ["SERVICE", "PORT", "REMOTE", "LOCAL", "PROTOCOL"],
+            ["web/api", "http", "80", "localhost:18080", "http"],
+            ["web/api", "-", "5432", "localhost:15432", "tcp"],
+            ["web/api", "grpc", "9000", "localhost:19000", "grpc"],
+        ];
+        assert_eq!(rows, expected.map(|row| row.map(String::from).to_vec()));
+    }
+
+    #[test]
+    fn yaml_lists_the_mappings() {
+        assert_eq!(
+            yaml::to_string(&forward_value(&spec(&[(5432, 15432), (80, 18080)]))),
+            "address: localhost\n\
+             ports:\n\
+             \x20 - local: 18080\n    name: http\n    protocol: http\n    remote: 80\n\
+             \x20 - local: 15432\n    name: null\n    protocol: tcp\n    remote: 5432\n\
+             service: web/api\n\
+             target: service/api\n"
+        );
+    }
+}
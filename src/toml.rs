@@ -0,0 +1,556 @@
+//! Minimal TOML support for the config, converting from and to JSON values.
+//! Covers what hand-edited configs use: tables, arrays of tables, inline tables, arrays,
+//! strings, integers, floats and booleans (no dates, which the config has no use for).
+
+use serde_json::{Map, Value};
+
+/// Renders the JSON object as TOML, dropping `null`s (which TOML can't express)
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    if let Value::Object(map) = value {
+        write_table(&mut out, &mut Vec::new(), map);
+    }
+    out
+}
+
+fn write_table(out: &mut String, path: &mut Vec<String>, map: &Map<String, Value>) {
+    for (key, value) in map {
+        if !value.is_null() && !value.is_object() {
+            out.push_str(&format!("{} = {}\n", key_string(key), value_string(value)));
+        }
+    }
+    for (key, value) in map {
+        if let Value::Object(table) = value {
+            path.push(key_string(key));
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("[{}]\n", path.join(".")));
+            write_table(out, path, table);
+            path.pop();
+        }
+    }
+}
+
+fn key_string(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_owned()
+    } else {
+        string_literal(key)
+    }
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => string_literal(s),
+        Value::Array(items) => {
+            let items = items
+                .iter()
+                .filter(|v| !v.is_null())
+                .map(value_string)
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let entries = map
+                .iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| format!("{} = {}", key_string(k), value_string(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        // NOTE: Numbers and booleans are written the same in JSON and TOML
+        other => other.to_string(),
+    }
+}
+
+fn string_literal(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parses TOML into a JSON object, failing with the line of the first error
+pub fn from_str(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    parser
+        .document()
+        .map_err(|e| format!("{e} at line {}", parser.line))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_str(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{expected}', found '{c}'")),
+            None => Err(format!("expected '{expected}', found the end")),
+        }
+    }
+
+    /// Skips spaces and tabs, and also newlines and comments if asked to
+    fn skip(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' => {}
+                '\r' | '\n' if newlines => {}
+                '#' if newlines => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.next();
+        }
+    }
+
+    /// Ends a line after a key/value pair or a table header
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip(false);
+        if self.peek() == Some('#') {
+            while self.peek().is_some_and(|c| c != '\n') {
+                self.next();
+            }
+        }
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.peek() == Some('\n') => self.expect('\n'),
+            Some(c) => Err(format!("expected the end of the line, found '{c}'")),
+        }
+    }
+
+    fn document(&mut self) -> Result<Value, String> {
+        let mut root = Value::Object(Map::new());
+        let mut current = Vec::new();
+        loop {
+            self.skip(true);
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') if self.peek_str("[[") => {
+                    self.pos += 2;
+                    current = self.header_key(true)?;
+                    let (last, parents) = current.split_last().ok_or("empty table name")?;
+                    let parent = table_at(&mut root, parents)?;
+                    let tables = parent
+                        .entry(last.to_owned())
+                        .or_insert_with(|| Value::Array(vec![]));
+                    match tables {
+                        Value::Array(items) => items.push(Value::Object(Map::new())),
+                        _ => return Err(format!("'{last}' is not an array of tables")),
+                    }
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    current = self.header_key(false)?;
+                    table_at(&mut root, &current)?;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip(false);
+                    self.expect('=')?;
+                    self.skip(false);
+                    let value = self.value()?;
+                    insert(table_at(&mut root, &current)?, &key, value)?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    fn header_key(&mut self, array: bool) -> Result<Vec<String>, String> {
+        self.skip(false);
+        let key = self.key()?;
+        self.skip(false);
+        self.expect(']')?;
+        if array {
+            self.expect(']')?;
+        }
+        self.end_of_line()?;
+        Ok(key)
+    }
+
+    /// Dotted key, e.g. `ports."web/api"`
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip(false);
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(String::from("expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            parts.push(part);
+            self.skip(false);
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.peek_str("\"\"\"") => self.multiline_basic_string().map(Value::from),
+            Some('"') => self.basic_string().map(Value::from),
+            Some('\'') if self.peek_str("'''") => self.multiline_literal_string().map(Value::from),
+            Some('\'') => self.literal_string().map(Value::from),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) if self.peek_str("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(_) if self.peek_str("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(_) => self.number(),
+            None => Err(String::from("expected a value")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip(true);
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip(true);
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(String::from("expected ',' or ']' in the array")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut table = Map::new();
+        self.skip(false);
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(table));
+        }
+        loop {
+            let key = self.key()?;
+            self.skip(false);
+            self.expect('=')?;
+            self.skip(false);
+            let value = self.value()?;
+            insert(&mut table, &key, value)?;
+            self.skip(false);
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(table)),
+                _ => return Err(String::from("expected ',' or '}' in the inline table")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || "+-._:".contains(c))
+        {
+            self.pos += 1;
+        }
+        let token = self.chars[start..self.pos].iter().collect::<String>();
+        if token.is_empty() {
+            return Err(String::from("expected a value"));
+        }
+        let invalid = || format!("invalid value '{token}'");
+        let digits = token.replace('_', "");
+        let (sign, unsigned) = match digits.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, digits.strip_prefix('+').unwrap_or(&digits)),
+        };
+        let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+            .iter()
+            .find_map(|(prefix, radix)| Some((unsigned.strip_prefix(prefix)?, *radix)));
+        if let Some((digits, radix)) = radix {
+            return i64::from_str_radix(digits, radix)
+                .map(|n| Value::from(sign * n))
+                .map_err(|_| invalid());
+        }
+        if token.contains(':') || unsigned.contains("inf") {
+            return Err(invalid());
+        }
+        if let Ok(n) = digits.parse::<i64>() {
+            return Ok(Value::from(n));
+        }
+        match digits.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Value::from(n)),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => out.push(self.escape()?),
+                Some('\n') | None => return Err(String::from("unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn multiline_basic_string(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.skip_first_newline();
+        let mut out = String::new();
+        loop {
+            if self.peek_str("\"\"\"") && !self.peek_str("\"\"\"\"") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            match self.next() {
+                // A backslash at the end of a line trims the line break and the indentation
+                Some('\\') if self.peek().is_some_and(|c| c.is_whitespace()) => self.skip(true),
+                Some('\\') => out.push(self.escape()?),
+                Some(c) => out.push(c),
+                None => return Err(String::from("unterminated string")),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(out),
+                Some('\n') | None => return Err(String::from("unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn multiline_literal_string(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.skip_first_newline();
+        let mut out = String::new();
+        loop {
+            if self.peek_str("'''") && !self.peek_str("''''") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            out.push(self.next().ok_or("unterminated string")?);
+        }
+    }
+
+    fn skip_first_newline(&mut self) {
+        if self.peek_str("\r\n") {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.next();
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.next().ok_or("unterminated string")?;
+        let unicode = |parser: &mut Self, len: usize| {
+            let hex = parser
+                .chars
+                .get(parser.pos..parser.pos + len)
+                .map(|h| h.iter().collect::<String>())
+                .ok_or("unterminated escape")?;
+            parser.pos += len;
+            u32::from_str_radix(&hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("invalid escape '\\{c}{hex}'"))
+        };
+        match c {
+            'b' => Ok('\u{8}'),
+            't' => Ok('\t'),
+            'n' => Ok('\n'),
+            'f' => Ok('\u{c}'),
+            'r' => Ok('\r'),
+            'e' => Ok('\u{1b}'),
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            'u' => unicode(self, 4),
+            'U' => unicode(self, 8),
+            c => Err(format!("invalid escape '\\{c}'")),
+        }
+    }
+}
+
+/// The table at the path, creating missing ones (arrays of tables lead to their last table)
+fn table_at<'a>(
+    root: &'a mut Value,
+    path: &[String],
+) -> Result<&'a mut Map<String, Value>, String> {
+    let mut value = root;
+    for key in path {
+        let Value::Object(table) = value else {
+            return Err(format!("'{key}' is not in a table"));
+        };
+        value = table
+            .entry(key.to_owned())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Array(items) = value {
+            value = items
+                .last_mut()
+                .ok_or_else(|| format!("'{key}' is not a table"))?;
+        }
+    }
+    match value {
+        Value::Object(table) => Ok(table),
+        _ => Err(format!("'{}' is not a table", path.join("."))),
+    }
+}
+
+fn insert(table: &mut Map<String, Value>, key: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = key.split_last().ok_or("empty key")?;
+    let mut table = table;
+    for part in parents {
+        let entry = table
+            .entry(part.to_owned())
+            .or_insert_with(|| Value::Object(Map::new()));
+        table = match entry {
+            Value::Object(inner) => inner,
+            _ => return Err(format!("'{part}' is not a table")),
+        };
+    }
+    if table.contains_key(last) {
+        return Err(format!("duplicate key '{last}'"));
+    }
+    table.insert(last.to_owned(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trips_a_config() {
+        let config = json!({
+            "namespace": "web",
+            "lastService": "web/api",
+            "ports": {"web/api": {"80": 8080, "443": 8443}, "db": {"5432": 5432}},
+            "servicePorts": {"web/api": [80, 443]},
+            "protectedContexts": ["prod-*", "live \"eu\""],
+            "fuzzy": false,
+            "sessionLog": "C:\\logs\\kpfr.jsonl",
+            "recentContexts": [],
+        });
+        let toml = to_string(&config);
+        assert!(toml.contains("[ports.\"web/api\"]\n"), "{toml}");
+        assert_eq!(from_str(&toml).unwrap(), config);
+    }
+
+    #[test]
+    fn parses_hand_written_toml() {
+        let toml = r#"
+            # Remembered selections
+            namespace = "web"   # the last one
+            protectedContexts = [
+                'prod-*',
+                "staging",  # trailing comma
+            ]
+            aliases = { db = "web/postgres", "api" = 'web/api' }
+
+            [ports."web/api"]
+            80 = 8_080
+            ratio = 0.5
+
+            [[sessions]]
+            note = """\
+            multi\
+              line"""
+        "#;
+        assert_eq!(
+            from_str(toml).unwrap(),
+            json!({
+                "namespace": "web",
+                "protectedContexts": ["prod-*", "staging"],
+                "aliases": {"db": "web/postgres", "api": "web/api"},
+                "ports": {"web/api": {"80": 8080, "ratio": 0.5}},
+                "sessions": [{"note": "multiline"}],
+            })
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        assert_eq!(
+            from_str("namespace = \"web\"\nfuzzy = yes\n").unwrap_err(),
+            "invalid value 'yes' at line 2"
+        );
+        assert!(
+            from_str("a = 1\na = 2")
+                .unwrap_err()
+                .contains("duplicate key 'a'")
+        );
+        assert!(from_str("started = 2026-10-16T00:00:00Z").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::cli;
+use crate::error::MainError;
+use crate::kubectl::{KubectlConfig, context, namespace, service};
+use crate::model::Service;
+use crate::selection::DefaultSelections;
+
+type Result<T> = std::result::Result<T, MainError>;
+
+/// What the cluster (of the current context) has of the things the config refers to
+struct Cluster {
+    current_context: String,
+    contexts: Vec<String>,
+    namespaces: BTreeSet<String>,
+    /// Services of the namespaces of remembered services
+    services: HashMap<String, Vec<Service>>,
+}
+
+/// Checks the remembered contexts, namespaces, services and ports against the cluster,
+/// printing every stale entry
+pub fn run(config: &KubectlConfig, defaults: &DefaultSelections) -> Result<()> {
+    let current_context = context::current()?;
+    let contexts = context::get()?;
+    let namespaces = namespace::get(config, None, None)?
+        .into_iter()
+        .map(|n| n.metadata.name)
+        .collect::<BTreeSet<_>>();
+    let mut services = HashMap::new();
+    for (_, namespace, _) in remembered_services(defaults) {
+        if namespaces.contains(namespace) && !services.contains_key(namespace) {
+            services.insert(namespace.to_owned(), service::get(config, namespace, None)?);
+        }
+    }
+    let cluster = Cluster {
+        current_context,
+        contexts,
+        namespaces,
+        services,
+    };
+
+    let stale = stale_entries(defaults, &cluster);
+    for message in &stale {
+        cli::print_stdout(message);
+    }
+    if stale.is_empty() {
+        eprintln!("Remembered config is up to date");
+        Ok(())
+    } else {
+        Err(MainError::StaleConfig(stale.len()))
+    }
+}
+
+/// Remembered service keys with their namespace and name, unqualified ones belong to the
+/// remembered namespace
+fn remembered_services(defaults: &DefaultSelections) -> Vec<(&str, &str, &str)> {
+    let keys = defaults
+        .ports
+        .iter()
+        .flat_map(|p| p.keys())
+        .chain(&defaults.last_service)
+        .collect::<BTreeSet<_>>();
+    keys.into_iter()
+        .filter_map(|key| {
+            let (namespace, name) = key
+                .split_once('/')
+                .or_else(|| defaults.namespace.as_deref().map(|ns| (ns, key.as_str())))?;
+            Some((key.as_str(), namespace, name))
+        })
+        .collect()
+}
+
+fn stale_entries(defaults: &DefaultSelections, cluster: &Cluster) -> Vec<String> {
+    let mut stale = Vec::new();
+    let exists = |context: &str| {
+        cluster
+            .contexts
+            .iter()
+            .any(|c| context::matches(c, context))
+    };
+
+    let current = &cluster.current_context;
+    if !exists(current) {
+        stale.push(format!("Current context '{current}' does not exist"));
+    }
+    for context in defaults.recent_contexts.iter().flatten() {
+        if !exists(context) {
+            stale.push(format!("Remembered context '{context}' does not exist"));
+        }
+    }
+
+    if let Some(namespace) = &defaults.namespace
+        && !cluster.namespaces.contains(namespace)
+    {
+        stale.push(format!("Namespace '{namespace}' does not exist"));
+    }
+
+    let ports = defaults.ports.clone().unwrap_or_default();
+    for (key, namespace, name) in remembered_services(defaults) {
+        let Some(services) = cluster.services.get(namespace) else {
+            stale.push(format!(
+                "Service '{key}' is in the missing namespace '{namespace}'"
+            ));
+            continue;
+        };
+        let Some(service) = services.iter().find(|s| s.metadata.name == name) else {
+            stale.push(format!("Service '{key}' does not exist"));
+            continue;
+        };
+        let mut remote_ports = ports
+            .get(key)
+            .map(|p| p.keys().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        remote_ports.sort();
+        for port in remote_ports {
+            if !service.spec.ports.iter().any(|p| p.port == port) {
+                stale.push(format!("Port {port} is not exposed by service '{key}'"));
+            }
+        }
+    }
+    stale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn defaults() -> DefaultSelections {
+        serde_json::from_value(json!({
+            "namespace": "web",
+            "lastService": "api",
+            "ports": {"web/api": {"80": 8080, "81": 8081}, "gone/db": {"5432": 5432}},
+            "recentContexts": ["dev", "old-cluster", "prod"],
+        }))
+        .unwrap()
+    }
+
+    fn cluster(namespaces: &[&str]) -> Cluster {
+        let api = serde_json::from_value(json!({
+            "metadata": {"name": "api", "namespace": "web"},
+            "spec": {"ports": [{"port": 80}]},
+        }))
+        .unwrap();
+        Cluster {
+            current_context: String::from("dev"),
+            contexts: vec![String::from("dev"), String::from("prod")],
+            namespaces: namespaces.iter().map(|n| n.to_string()).collect(),
+            services: HashMap::from([(String::from("web"), vec![api])]),
+        }
+    }
+
+    #[test]
+    fn reports_every_stale_entry() {
+        assert_eq!(
+            stale_entries(&defaults(), &cluster(&["web"])),
+            [
+                "Remembered context 'old-cluster' does not exist",
+                "Service 'gone/db' is in the missing namespace 'gone'",
+                "Port 81 is not exposed by service 'web/api'",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_a_missing_remembered_namespace() {
+        let cluster = Cluster {
+            current_context: String::from("deleted"),
+            services: HashMap::new(),
+            ..cluster(&["default"])
+        };
+        let stale = stale_entries(&defaults(), &cluster);
+        assert_eq!(stale[0], "Current context 'deleted' does not exist");
+        assert!(stale.contains(&String::from("Namespace 'web' does not exist")));
+        assert!(stale.contains(&String::from(
+            "Service 'api' is in the missing namespace 'web'"
+        )));
+    }
+
+    #[test]
+    fn up_to_date_config_has_no_stale_entries() {
+        let defaults = serde_json::from_value(json!({
+            "namespace": "web",
+            "lastService": "web/api",
+            "ports": {"web/api": {"80": 8080}},
+            "recentContexts": ["prod", "dev"],
+        }))
+        .unwrap();
+        assert!(stale_entries(&defaults, &cluster(&["web"])).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! Minimal YAML output of JSON values, in block style
+
+use serde_json::Value;
+
+/// Renders the value as a YAML document
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_block(&mut out, value, 0),
+        Value::Array(items) if !items.is_empty() => write_block(&mut out, value, 0),
+        scalar => {
+            out.push_str(&scalar_string(scalar));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn write_block(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                out.push_str(&format!("{pad}{}:", string_scalar(key)));
+                write_nested(out, value, indent);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                out.push_str(&format!("{pad}-"));
+                match item {
+                    // NOTE: The first entry of a mapping goes on the line of the dash
+                    Value::Object(map) if !map.is_empty() => {
+                        let mut entries = String::new();
+                        write_block(&mut entries, item, indent + 2);
+                        out.push(' ');
+                        out.push_str(&entries[indent + 2..]);
+                    }
+                    _ => write_nested(out, item, indent),
+                }
+            }
+        }
+        _ => unreachable!("only collections are written as blocks"),
+    }
+}
+
+/// Writes the value after `key:` or `-`, nesting non-empty collections below
+fn write_nested(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_block(out, value, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_block(out, value, indent + 2);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&scalar_string(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("null"),
+        Value::String(s) => string_scalar(s),
+        Value::Object(_) => String::from("{}"),
+        Value::Array(_) => String::from("[]"),
+        other => other.to_string(),
+    }
+}
+
+/// Plain string unless YAML would read it as something else, then double-quoted
+fn string_scalar(s: &str) -> String {
+    let ambiguous = matches!(
+        s.to_ascii_lowercase().as_str(),
+        "" | "~" | "null" | "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n"
+    ) || s.parse::<f64>().is_ok()
+        || s.starts_with(|c: char| c.is_whitespace() || "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        || s.ends_with(char::is_whitespace)
+        || s.contains(": ")
+        || s.contains(" #")
+        || s.ends_with(':')
+        || s.contains(char::is_control);
+    if ambiguous {
+        // NOTE: JSON strings are valid double-quoted YAML scalars
+        Value::from(s).to_string()
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn nests_mappings_and_sequences() {
+        let value = json!({
+            "service": "web/api",
+            "ports": [{"local": 8080, "remote": 80}, {"local": 8443, "remote": 443}],
+            "candidates": ["prod-eu", "prod-us"],
+            "empty": [],
+            "detail": null,
+        });
+        assert_eq!(
+            to_string(&value),
+            "candidates:\n  - prod-eu\n  - prod-us\n\
+             detail: null\n\
+             empty: []\n\
+             ports:\n  - local: 8080\n    remote: 80\n  - local: 8443\n    remote: 443\n\
+             service: web/api\n"
+        );
+    }
+
+    #[test]
+    fn quotes_ambiguous_strings() {
+        let value = json!([
+            "80",
+            "yes",
+            "",
+            "- x",
+            "a: b",
+            "line\nbreak",
+            "*prod*",
+            "prod-*"
+        ]);
+        assert_eq!(
+            to_string(&value),
+            "- \"80\"\n- \"yes\"\n- \"\"\n- \"- x\"\n- \"a: b\"\n- \"line\\nbreak\"\n- \"*prod*\"\n- prod-*\n"
+        );
+    }
+}